@@ -0,0 +1,24 @@
+use std::env;
+
+use tftp::Server;
+
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1);
+    let addr = args.next().unwrap();
+    let wd = args.next().unwrap();
+
+    let server = Server::new(addr.clone(), wd).unwrap().into_async().unwrap();
+    println!("Serving Trivial File Transfer Protocol (TFTP) @ {}", addr);
+
+    while let Ok(h) = server.serve().await {
+        println!("Handling request...");
+
+        tokio::spawn(async move {
+            match h.handle().await {
+                Ok(()) => println!("OK"),
+                Err(e) => println!("FAIL: {:?}", e),
+            }
+        });
+    }
+}
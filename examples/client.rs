@@ -1,11 +1,11 @@
 use std::env;
 use std::fs::File;
-use std::io::{Result, Write};
+use std::io::Write;
 use std::path::Path;
 
 use tftp::client;
 use tftp::packet::Mode;
-use tftp::Client;
+use tftp::{Client, Result};
 
 fn put<T: AsRef<Path>>(src: T, client: Client) {
     let target = src
@@ -20,7 +20,7 @@ fn put<T: AsRef<Path>>(src: T, client: Client) {
     client.put(target, Mode::NetAscii, source).unwrap();
 }
 
-fn get<T: AsRef<str>, W: Write>(file: T, client: Client, write: W) -> Result<W> {
+fn get<T: AsRef<[u8]>, W: Write>(file: T, client: Client, write: W) -> Result<W> {
     client.get(file, Mode::NetAscii, write)
 }
 
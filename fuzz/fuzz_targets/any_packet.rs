@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tftp::packet::AnyPacket;
+
+fuzz_target!(|bytes: &[u8]| {
+    // Whatever parses has to survive being encoded and parsed again.
+    if let Ok(packet) = AnyPacket::from_bytes(bytes) {
+        let reparsed = AnyPacket::from_bytes(packet.clone().into_bytes()).unwrap();
+        assert_eq!(reparsed, packet);
+    }
+});
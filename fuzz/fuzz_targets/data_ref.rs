@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tftp::packet::{AnyPacket, DataRef};
+
+fuzz_target!(|bytes: &[u8]| {
+    // The borrowed view has to agree with the owned packet.
+    match (DataRef::parse(bytes), AnyPacket::from_bytes(bytes)) {
+        (Ok(view), Ok(AnyPacket::Data(data))) => {
+            assert_eq!(view.to_packet(), data);
+
+            let mut buf = vec![0; view.encoded_len()];
+            view.encode(&mut buf).unwrap();
+            assert_eq!(buf, bytes);
+        }
+        (Ok(view), packet) => panic!("parsed {:?} as {:?}", view, packet),
+        _ => {}
+    }
+});
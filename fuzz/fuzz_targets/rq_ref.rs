@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tftp::packet::{AnyPacket, RqRef};
+
+fuzz_target!(|bytes: &[u8]| {
+    // The borrowed view has to agree with the owned packet.
+    if let Ok(view) = RqRef::parse(bytes) {
+        let rq = match AnyPacket::from_bytes(bytes) {
+            Ok(AnyPacket::Rrq(rrq)) => rrq.body.0,
+            Ok(AnyPacket::Wrq(wrq)) => wrq.body.0,
+            packet => panic!("parsed {:?} as {:?}", view, packet),
+        };
+
+        assert_eq!(view.filename, &rq.filename[..]);
+        assert_eq!(view.mode, rq.mode);
+        assert!(view.options().eq(rq.options.iter()));
+
+        let mut buf = vec![0; view.encoded_len()];
+        view.encode(&mut buf).unwrap();
+        let reparsed = RqRef::parse(&buf).unwrap();
+        assert_eq!(reparsed, view);
+    }
+});
@@ -0,0 +1,568 @@
+//! Who may read and write which files.
+//!
+//! A `Server` asks its `AccessPolicy` about every request before it opens
+//! anything, and refuses the request with the code the policy answers with.
+//! Besides closures, a few policies are built in:
+//!
+//! - `ReadOnly` refuses every write, and `WriteOnly` every read, which makes
+//!   a drop box.
+//! - `Subnets` lets in clients by the networks they are on.
+//! - `Paths` lets in requests by the names of their files.
+//! - `Reads` and `Writes` apply a policy to one direction only, and a tuple
+//!   of policies lets in what all of them let in.
+//!
+//! ```no_run
+//! use tftp::access::{Subnets, Writes};
+//! use tftp::Server;
+//!
+//! # fn run() -> tftp::Result<()> {
+//! // Anyone may read, but only the backup subnet may upload.
+//! let backups = Subnets::allow(vec!["10.20.0.0/24".parse()?]);
+//! let server = Server::new("0.0.0.0:69", "/srv/tftp")?.access_policy(Writes(backups));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::packet::{Code, Mode};
+use crate::{Error, Result};
+
+/// Whether a file is to be read or written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// A read request; the client gets a file.
+    Read,
+
+    /// A write request; the client puts a file.
+    Write,
+}
+
+/// A request for a file, as an `AccessPolicy` sees it.
+#[derive(Clone, Copy, Debug)]
+pub struct Access<'a> {
+    /// The address of the client.
+    pub client: SocketAddr,
+
+    /// The name of the file, as a relative path made up of nothing but
+    /// names.
+    pub name: &'a Path,
+
+    /// The mode of the transfer.
+    pub mode: Mode,
+
+    /// Whether the file is to be read or written.
+    pub direction: Direction,
+}
+
+/// Decides which requests a server grants.
+///
+/// A policy may be called from several threads at once, and from the tasks
+/// of an `AsyncServer`, so it should not block.
+pub trait AccessPolicy: Send + Sync {
+    /// Grants `access`, or refuses it with the code the client is told,
+    /// which is usually `Code::AccessViolation`.
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code>;
+}
+
+impl<F> AccessPolicy for F
+where
+    F: Fn(&Access<'_>) -> std::result::Result<(), Code> + Send + Sync,
+{
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        self(access)
+    }
+}
+
+/// Grants every request, which is what a server does unless told otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+impl AccessPolicy for AllowAll {
+    fn check(&self, _access: &Access<'_>) -> std::result::Result<(), Code> {
+        Ok(())
+    }
+}
+
+/// Refuses every write request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReadOnly;
+
+impl AccessPolicy for ReadOnly {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        match access.direction {
+            Direction::Read => Ok(()),
+            Direction::Write => Err(Code::AccessViolation),
+        }
+    }
+}
+
+/// Refuses every read request, so that clients can drop files off but never
+/// see what others dropped off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOnly;
+
+impl AccessPolicy for WriteOnly {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        match access.direction {
+            Direction::Read => Err(Code::AccessViolation),
+            Direction::Write => Ok(()),
+        }
+    }
+}
+
+/// Applies a policy to read requests only, and grants every write request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Reads<P>(pub P);
+
+impl<P: AccessPolicy> AccessPolicy for Reads<P> {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        match access.direction {
+            Direction::Read => self.0.check(access),
+            Direction::Write => Ok(()),
+        }
+    }
+}
+
+/// Applies a policy to write requests only, and grants every read request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Writes<P>(pub P);
+
+impl<P: AccessPolicy> AccessPolicy for Writes<P> {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        match access.direction {
+            Direction::Read => Ok(()),
+            Direction::Write => self.0.check(access),
+        }
+    }
+}
+
+/// A tuple of policies grants what all of them grant, and is refused with
+/// the code of the first that refuses.
+macro_rules! impl_all {
+    ($($policy:ident),+) => {
+        impl<$($policy: AccessPolicy),+> AccessPolicy for ($($policy,)+) {
+            #[allow(non_snake_case)]
+            fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+                let ($($policy,)+) = self;
+                $($policy.check(access)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_all!(A, B);
+impl_all!(A, B, C);
+impl_all!(A, B, C, D);
+
+/// A network of IPv4 or IPv6 addresses, such as `192.168.1.0/24`.
+///
+/// IPv4 networks also hold the IPv4-mapped IPv6 addresses of their
+/// addresses, which is how clients show up on a server bound to `[::]`.
+/// Networks written as IPv4-mapped IPv6 addresses, such as
+/// `::ffff:10.20.0.0/120`, are the IPv4 networks they map to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// The network of the addresses whose first `prefix_len` bits are those
+    /// of `addr`, or `None` if `addr` has fewer bits than that.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > bits {
+            return None;
+        }
+
+        // Clients are matched by their IPv4 address, so the network has to
+        // be one of IPv4 addresses too.
+        if let IpAddr::V6(v6) = addr {
+            if let (Some(v4), Some(prefix_len)) = (v6.to_ipv4_mapped(), prefix_len.checked_sub(96))
+            {
+                return Some(Self {
+                    addr: IpAddr::V4(v4),
+                    prefix_len,
+                });
+            }
+        }
+
+        Some(Self { addr, prefix_len })
+    }
+
+    /// Whether `addr` is within this network.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+            addr => addr,
+        };
+
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => prefix_eq(
+                u32::from(net).into(),
+                u32::from(addr).into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                prefix_eq(net.into(), addr.into(), 128, self.prefix_len)
+            }
+            // Networks wider than the mapped addresses, such as `::/0`.
+            (IpAddr::V6(net), IpAddr::V4(addr)) => prefix_eq(
+                net.into(),
+                addr.to_ipv6_mapped().into(),
+                128,
+                self.prefix_len,
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Whether the first `prefix_len` of the `bits` bits of `a` and `b` agree.
+fn prefix_eq(a: u128, b: u128, bits: u8, prefix_len: u8) -> bool {
+    let shift = u32::from(bits - prefix_len);
+    a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+}
+
+/// Parses `address/prefix-length`, or a lone address for a network of one.
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || -> Error {
+            io::Error::new(ErrorKind::InvalidInput, format!("invalid network {:?}", s)).into()
+        };
+
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => {
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                (addr, len.parse().map_err(|_| invalid())?)
+            }
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+                (addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        };
+
+        Cidr::new(addr, prefix_len).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Lets in clients by the networks they are on.
+///
+/// Clients that are let out are refused with `Code::AccessViolation`.
+#[derive(Clone, Debug)]
+pub struct Subnets {
+    networks: Vec<Cidr>,
+    allow: bool,
+}
+
+impl Subnets {
+    /// Lets in the clients on `networks` only.
+    pub fn allow<I: IntoIterator<Item = Cidr>>(networks: I) -> Self {
+        Self {
+            networks: networks.into_iter().collect(),
+            allow: true,
+        }
+    }
+
+    /// Lets in every client but those on `networks`.
+    pub fn deny<I: IntoIterator<Item = Cidr>>(networks: I) -> Self {
+        Self {
+            networks: networks.into_iter().collect(),
+            allow: false,
+        }
+    }
+}
+
+impl AccessPolicy for Subnets {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        let ip = access.client.ip();
+        let listed = self.networks.iter().any(|net| net.contains(ip));
+
+        if listed == self.allow {
+            Ok(())
+        } else {
+            Err(Code::AccessViolation)
+        }
+    }
+}
+
+/// A pattern for the names of files.
+///
+/// Components of names are separated by `/`. `?` matches any one character
+/// and `*` any number of them, but neither matches a `/`. `**` matches any
+/// number of characters, `/` included, and `**/` any number of leading
+/// directories, none included. Any other character matches itself.
+///
+/// ```
+/// use std::path::Path;
+/// use tftp::access::Glob;
+///
+/// let glob = Glob::new("backups/**/*.cfg");
+/// assert!(glob.matches(Path::new("backups/switch-01.cfg")));
+/// assert!(glob.matches(Path::new("backups/rack-4/switch-01.cfg")));
+/// assert!(!glob.matches(Path::new("firmware/switch-01.cfg")));
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token {
+    Char(char),
+    One,
+    Many,
+    Any,
+    Dirs,
+}
+
+impl Glob {
+    /// Compiles `pattern`.
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::One,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::Dirs
+                    } else {
+                        Token::Any
+                    }
+                }
+                '*' => Token::Many,
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Self {
+            pattern: pattern.to_string(),
+            tokens,
+        }
+    }
+
+    /// Whether `name`, with its components joined by `/`, matches the
+    /// pattern. Names that are not valid UTF-8 are matched as if their
+    /// invalid bytes were U+FFFD.
+    pub fn matches(&self, name: &Path) -> bool {
+        let name: Vec<char> = name
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+
+        // matched[j]: whether the tokens so far match the first j characters.
+        let mut matched = vec![false; name.len() + 1];
+        matched[0] = true;
+
+        for token in &self.tokens {
+            let mut next = vec![false; name.len() + 1];
+            let mut any_before = false;
+
+            for j in 0..=name.len() {
+                any_before |= matched[j];
+                next[j] = match *token {
+                    Token::Char(c) => j > 0 && matched[j - 1] && name[j - 1] == c,
+                    Token::One => j > 0 && matched[j - 1] && name[j - 1] != '/',
+                    Token::Many => matched[j] || (j > 0 && next[j - 1] && name[j - 1] != '/'),
+                    Token::Any => any_before,
+                    Token::Dirs => matched[j] || (j > 0 && name[j - 1] == '/' && any_before),
+                };
+            }
+
+            matched = next;
+        }
+
+        matched[name.len()]
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Lets in requests by the names of their files.
+///
+/// Requests that are let out are refused with `Code::AccessViolation`.
+#[derive(Clone, Debug)]
+pub struct Paths {
+    globs: Vec<Glob>,
+    allow: bool,
+}
+
+impl Paths {
+    /// Lets in requests for the files that match one of `globs` only.
+    pub fn allow<I: IntoIterator<Item = Glob>>(globs: I) -> Self {
+        Self {
+            globs: globs.into_iter().collect(),
+            allow: true,
+        }
+    }
+
+    /// Lets in every request but those for the files that match one of
+    /// `globs`.
+    pub fn deny<I: IntoIterator<Item = Glob>>(globs: I) -> Self {
+        Self {
+            globs: globs.into_iter().collect(),
+            allow: false,
+        }
+    }
+}
+
+impl AccessPolicy for Paths {
+    fn check(&self, access: &Access<'_>) -> std::result::Result<(), Code> {
+        let listed = self.globs.iter().any(|glob| glob.matches(access.name));
+
+        if listed == self.allow {
+            Ok(())
+        } else {
+            Err(Code::AccessViolation)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(client: &str, name: &'static str, direction: Direction) -> Access<'static> {
+        Access {
+            client: client.parse().unwrap(),
+            name: Path::new(name),
+            mode: Mode::Octet,
+            direction,
+        }
+    }
+
+    #[test]
+    fn test_directions() {
+        let read = access("10.0.0.1:69", "file", Direction::Read);
+        let write = access("10.0.0.1:69", "file", Direction::Write);
+
+        assert_eq!(ReadOnly.check(&read), Ok(()));
+        assert_eq!(ReadOnly.check(&write), Err(Code::AccessViolation));
+        assert_eq!(WriteOnly.check(&read), Err(Code::AccessViolation));
+        assert_eq!(WriteOnly.check(&write), Ok(()));
+
+        assert_eq!(Writes(ReadOnly).check(&write), Err(Code::AccessViolation));
+        assert_eq!(Reads(ReadOnly).check(&write), Ok(()));
+        assert_eq!(Reads(WriteOnly).check(&read), Err(Code::AccessViolation));
+
+        let closure = |_: &Access<'_>| Err(Code::NoSuchUser);
+        assert_eq!((AllowAll, closure).check(&read), Err(Code::NoSuchUser));
+        assert_eq!((AllowAll, ReadOnly, AllowAll).check(&read), Ok(()));
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "192.168.1.0/24".parse().unwrap();
+        assert!(net.contains("192.168.1.200".parse().unwrap()));
+        assert!(!net.contains("192.168.2.1".parse().unwrap()));
+        assert!(net.contains("::ffff:192.168.1.7".parse().unwrap()));
+        assert!(!net.contains("fe80::1".parse().unwrap()));
+        assert_eq!(net.to_string(), "192.168.1.0/24");
+
+        let host: Cidr = "10.0.0.1".parse().unwrap();
+        assert!(host.contains("10.0.0.1".parse().unwrap()));
+        assert!(!host.contains("10.0.0.2".parse().unwrap()));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("8.8.8.8".parse().unwrap()));
+
+        let net: Cidr = "fd00:1::/32".parse().unwrap();
+        assert!(net.contains("fd00:1:ffff::1".parse().unwrap()));
+        assert!(!net.contains("fd00:2::1".parse().unwrap()));
+
+        // A network written the way clients show up on a server bound to
+        // `[::]` still holds them.
+        let mapped: Cidr = "::ffff:10.20.0.0/120".parse().unwrap();
+        assert_eq!(mapped, "10.20.0.0/24".parse().unwrap());
+        assert!(mapped.contains("10.20.0.9".parse().unwrap()));
+        assert!(mapped.contains("::ffff:10.20.0.9".parse().unwrap()));
+        assert!(!mapped.contains("::ffff:10.21.0.9".parse().unwrap()));
+
+        let everything: Cidr = "::/0".parse().unwrap();
+        assert!(everything.contains("::ffff:8.8.8.8".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/".parse::<Cidr>().is_err());
+        assert!("backups".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_subnets() {
+        let backups = Subnets::allow(vec!["10.20.0.0/24".parse().unwrap()]);
+        let inside = access("10.20.0.9:1234", "file", Direction::Write);
+        let outside = access("10.30.0.9:1234", "file", Direction::Write);
+        assert_eq!(backups.check(&inside), Ok(()));
+        assert_eq!(backups.check(&outside), Err(Code::AccessViolation));
+
+        let guests = Subnets::deny(vec!["10.30.0.0/16".parse().unwrap()]);
+        assert_eq!(guests.check(&inside), Ok(()));
+        assert_eq!(guests.check(&outside), Err(Code::AccessViolation));
+    }
+
+    #[test]
+    fn test_glob() {
+        let matches = |pattern: &str, name: &str| Glob::new(pattern).matches(Path::new(name));
+
+        assert!(matches("file.cfg", "file.cfg"));
+        assert!(!matches("file.cfg", "file.cfgx"));
+        assert!(matches("*.cfg", "switch.cfg"));
+        assert!(matches("*.cfg", ".cfg"));
+        assert!(!matches("*.cfg", "backups/switch.cfg"));
+        assert!(matches("switch-0?.cfg", "switch-01.cfg"));
+        assert!(!matches("switch-0?.cfg", "switch-0/.cfg"));
+        assert!(matches("backups/*", "backups/a"));
+        assert!(!matches("backups/*", "backups/a/b"));
+        assert!(matches("backups/**", "backups/a/b"));
+        assert!(matches("**/*.bin", "firmware.bin"));
+        assert!(matches("**/*.bin", "a/b/firmware.bin"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "ab"));
+        assert!(matches("**", "anything/at/all"));
+    }
+
+    #[test]
+    fn test_paths() {
+        let firmware = Paths::deny(vec![Glob::new("secrets/**")]);
+        let public = access("10.0.0.1:69", "firmware.bin", Direction::Read);
+        let secret = access("10.0.0.1:69", "secrets/key", Direction::Read);
+        assert_eq!(firmware.check(&public), Ok(()));
+        assert_eq!(firmware.check(&secret), Err(Code::AccessViolation));
+
+        let configs = Paths::allow(vec![Glob::new("*.cfg"), Glob::new("pxelinux.cfg/*")]);
+        let config = access("10.0.0.1:69", "pxelinux.cfg/default", Direction::Read);
+        assert_eq!(configs.check(&config), Ok(()));
+        assert_eq!(configs.check(&public), Err(Code::AccessViolation));
+    }
+}
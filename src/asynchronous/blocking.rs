@@ -0,0 +1,187 @@
+//! Blocking readers and writers, such as the files of a `Storage`, driven
+//! from a tokio runtime without blocking it.
+
+use std::future::{poll_fn, Future};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Op {
+    Read,
+    Write,
+    Flush,
+}
+
+type Running<T> = (Op, JoinHandle<(T, Vec<u8>, io::Result<()>)>);
+
+/// Runs the reads and writes of `T` on tokio's blocking thread pool, one at
+/// a time, the way `tokio::fs::File` does for files.
+///
+/// A write is reported as done as soon as it has been handed over, and its
+/// error, if any, is reported by the next write or flush instead.
+pub(crate) struct Blocking<T> {
+    /* `None` while an operation is running, or after one panicked */
+    inner: Option<T>,
+    running: Option<Running<T>>,
+
+    /* what was read ahead of the caller */
+    buf: Vec<u8>,
+    pos: usize,
+
+    flushing: bool,
+}
+
+impl<T: Send + 'static> Blocking<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self {
+            inner: Some(inner),
+            running: None,
+            buf: Vec::new(),
+            pos: 0,
+            flushing: false,
+        }
+    }
+
+    /// Waits for the running operation, whose outcome has either been
+    /// reported already or no longer matters, and returns `T`.
+    pub(crate) async fn into_inner(mut self) -> io::Result<T> {
+        let _ = poll_fn(|cx| self.poll_idle(cx)).await;
+        self.inner.take().ok_or_else(lost)
+    }
+
+    /// Waits for the running operation, if any, and returns its outcome.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let (op, handle) = match self.running {
+            Some(ref mut running) => running,
+            None => return Poll::Ready(Ok(())),
+        };
+
+        let op = *op;
+        let joined = ready!(Pin::new(handle).poll(cx));
+        self.running = None;
+
+        let (inner, buf, result) = joined.map_err(io::Error::other)?;
+        self.inner = Some(inner);
+        if op == Op::Read && result.is_ok() {
+            self.buf = buf;
+            self.pos = 0;
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn spawn<F>(&mut self, op: Op, mut buf: Vec<u8>, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut T, &mut Vec<u8>) -> io::Result<()> + Send + 'static,
+    {
+        let mut inner = self.inner.take().ok_or_else(lost)?;
+        let handle = tokio::task::spawn_blocking(move || {
+            let result = f(&mut inner, &mut buf);
+            (inner, buf, result)
+        });
+
+        self.running = Some((op, handle));
+        Ok(())
+    }
+}
+
+impl<T: Read + Send + Unpin + 'static> AsyncRead for Blocking<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.pos < self.buf.len() {
+                let len = dst.remaining().min(self.buf.len() - self.pos);
+                dst.put_slice(&self.buf[self.pos..self.pos + len]);
+                self.pos += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if self.running.is_some() {
+                ready!(self.poll_idle(cx))?;
+                if self.pos == self.buf.len() {
+                    // Nothing was read, which marks the end of the input.
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+
+            if dst.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let buf = vec![0; dst.remaining()];
+            self.spawn(Op::Read, buf, |inner, buf| {
+                let n = inner.read(buf)?;
+                buf.truncate(n);
+                Ok(())
+            })?;
+        }
+    }
+}
+
+impl<T: Write + Send + Unpin + 'static> AsyncWrite for Blocking<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        src: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_idle(cx))?;
+
+        self.spawn(Op::Write, src.to_vec(), |inner, buf| inner.write_all(buf))?;
+        Poll::Ready(Ok(src.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.flushing {
+            ready!(self.poll_idle(cx))?;
+            self.spawn(Op::Flush, Vec::new(), |inner, _| inner.flush())?;
+            self.flushing = true;
+        }
+
+        let result = ready!(self.poll_idle(cx));
+        self.flushing = false;
+        Poll::Ready(result)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+fn lost() -> io::Error {
+    io::Error::other("file lost to a panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_read() {
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mut reader = Blocking::new(Cursor::new(data.clone()));
+
+        let mut actual = Vec::new();
+        reader.read_to_end(&mut actual).await.unwrap();
+        assert_eq!(actual, data);
+    }
+
+    #[tokio::test]
+    async fn test_write() {
+        let mut writer = Blocking::new(Vec::new());
+        writer.write_all(b"hello, ").await.unwrap();
+        writer.write_all(b"world").await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(writer.into_inner().await.unwrap(), b"hello, world");
+    }
+}
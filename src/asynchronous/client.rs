@@ -0,0 +1,259 @@
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::UdpSocket;
+use tokio::time;
+
+use super::connection::AsyncConnection;
+use crate::bytes::IntoBytes;
+use crate::machine::{self, Failure, Receiver, Sender};
+use crate::negotiate::{self, TransferOptions};
+use crate::netascii;
+use crate::packet::*;
+use crate::{Error, Result};
+
+/// Represents a single connection with a TFTP server, like `Client`, but
+/// for use on a tokio runtime.
+///
+/// An `AsyncClient` is configured with the same `Builder` as a `Client`; see
+/// `Builder::build_async`.
+pub struct AsyncClient {
+    server: Vec<SocketAddr>,
+    socket: UdpSocket,
+    options: Options,
+    transfer: TransferOptions,
+}
+
+impl AsyncClient {
+    pub(crate) fn new(
+        server: Vec<SocketAddr>,
+        socket: UdpSocket,
+        options: Options,
+        transfer: TransferOptions,
+    ) -> Self {
+        Self {
+            server,
+            socket,
+            options,
+            transfer,
+        }
+    }
+
+    /// Retrieves a file from the remote server.
+    ///
+    /// The filename is sent as it is, so it need not be valid UTF-8, but a
+    /// filename that contains a nul byte is refused.
+    ///
+    /// In `NetAscii` mode, line endings are translated as the file is
+    /// received.
+    pub async fn get<S: AsRef<[u8]>, W: AsyncWrite + Unpin>(
+        self,
+        file: S,
+        mode: Mode,
+        writer: W,
+    ) -> Result<W> {
+        self.receive(file, mode, writer, |_, _| ()).await
+    }
+
+    /// Retrieves a file from the remote server, like `get`, and asks the
+    /// server for its size (RFC 2349) along the way; see
+    /// `Client::get_with_size`.
+    pub async fn get_with_size<S, W, F>(
+        mut self,
+        file: S,
+        mode: Mode,
+        writer: W,
+        on_size: F,
+    ) -> Result<W>
+    where
+        S: AsRef<[u8]>,
+        W: AsyncWrite + Unpin,
+        F: FnOnce(Option<u64>, &mut W),
+    {
+        self.options.insert(negotiate::TSIZE, "0");
+        self.receive(file, mode, writer, on_size).await
+    }
+
+    async fn receive<S, W, F>(self, file: S, mode: Mode, mut writer: W, on_size: F) -> Result<W>
+    where
+        S: AsRef<[u8]>,
+        W: AsyncWrite + Unpin,
+        F: FnOnce(Option<u64>, &mut W),
+    {
+        // An `Oack` must be acknowledged before the server sends any data.
+        let (options, server) = self.request_read(file, mode).await?;
+        on_size(
+            options.and_then(|options| options.transfer_size),
+            &mut writer,
+        );
+
+        let receiver = match options {
+            Some(options) => {
+                let ack = Packet::ack(Block::new(0));
+                Receiver::new(options, Some(ack.into_bytes()))
+            }
+            None => Receiver::new(self.transfer, None),
+        };
+
+        let conn = AsyncConnection::new(self.socket, server);
+        match mode {
+            Mode::NetAscii => {
+                let decoder = conn.get(receiver, netascii::Decoder::new(writer)).await?;
+                Ok(decoder.finish_async().await?)
+            }
+            _ => conn.get(receiver, writer).await,
+        }
+    }
+
+    /// Asks the remote server for the size of a file (RFC 2349) without
+    /// retrieving it.
+    ///
+    /// Returns `None` if the server does not support the transfer size
+    /// option.
+    ///
+    /// This costs a round trip of its own; see `Client::size`.
+    pub async fn size<S: AsRef<[u8]>>(mut self, file: S, mode: Mode) -> Result<Option<u64>> {
+        self.options.insert(negotiate::TSIZE, "0");
+
+        let (options, server) = self.request_read(file, mode).await?;
+        let size = options.and_then(|options| options.transfer_size);
+
+        // Either way, the server is waiting for this transfer to proceed.
+        let error = Packet::error(Code::NotDefined, "transfer size probe complete");
+        let _ = self.socket.send_to(&error.into_bytes()[..], server).await;
+
+        Ok(size)
+    }
+
+    /// Stores a file on the remote server.
+    ///
+    /// As with `get`, the filename need not be valid UTF-8.
+    ///
+    /// In `NetAscii` mode, line endings are translated as the file is sent.
+    pub async fn put<S: AsRef<[u8]>, R: AsyncRead + Unpin>(
+        self,
+        file: S,
+        mode: Mode,
+        reader: R,
+    ) -> Result<()> {
+        let wrq = Packet::try_wrq(file, mode, self.options.clone())?;
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, server) = self.request(&wrq.into_bytes()[..], &mut buf, false).await?;
+
+        let options = match machine::answer_write(&buf[..nbytes], &self.options, self.transfer) {
+            Ok(options) => options,
+            Err(failure) => return Err(self.abort(failure, server).await),
+        };
+
+        let sender = Sender::new(options, None);
+        let conn = AsyncConnection::new(self.socket, server);
+        match mode {
+            Mode::NetAscii => conn.put(sender, netascii::Encoder::new(reader)).await,
+            _ => conn.put(sender, reader).await,
+        }
+    }
+
+    /// Stores a file of a known size on the remote server, announcing its
+    /// size (RFC 2349) so that the server may refuse it up front.
+    pub async fn put_with_size<S: AsRef<[u8]>, R: AsyncRead + Unpin>(
+        mut self,
+        file: S,
+        mode: Mode,
+        reader: R,
+        size: u64,
+    ) -> Result<()> {
+        self.options.insert(negotiate::TSIZE, size.to_string());
+        self.put(file, mode, reader).await
+    }
+
+    /// Sends a read request and waits for the server to answer it.
+    ///
+    /// Returns the negotiated options if the server answered with an `Oack`,
+    /// which is yet to be acknowledged. Otherwise the server's first `Data`
+    /// packet is left in the socket. Either way, the server's Transfer ID is
+    /// returned as well.
+    async fn request_read<S: AsRef<[u8]>>(
+        &self,
+        file: S,
+        mode: Mode,
+    ) -> Result<(Option<TransferOptions>, SocketAddr)> {
+        let rrq = Packet::try_rrq(file, mode, self.options.clone())?;
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, server) = self.request(&rrq.into_bytes()[..], &mut buf, true).await?;
+
+        let answer = machine::answer_read(&buf[..nbytes], &self.options, self.transfer);
+
+        // Only the first `Data` packet is left for the transfer to receive.
+        if !matches!(answer, Ok(None)) {
+            let _ = self.socket.recv(&mut buf).await?;
+        }
+
+        match answer {
+            Ok(options) => Ok((options, server)),
+            Err(failure) => Err(self.abort(failure, server).await),
+        }
+    }
+
+    /// Sends a request to the server, retransmitting it until the server
+    /// answers, and returns the Transfer ID the answer came from. The rest
+    /// of the transfer is expected to come from there as well.
+    ///
+    /// As with `Client`, answers from any host but the server's are refused
+    /// with an error.
+    ///
+    /// If `peek` is set, the answer is left in the socket.
+    async fn request(
+        &self,
+        request: &[u8],
+        buf: &mut [u8],
+        peek: bool,
+    ) -> Result<(usize, SocketAddr)> {
+        let mut retries = self.transfer.retries;
+        loop {
+            let _ = self.socket.send_to(request, &self.server[..]).await?;
+            let deadline = time::Instant::now() + self.transfer.timeout;
+
+            loop {
+                let answer = if peek {
+                    time::timeout_at(deadline, self.socket.peek_from(buf)).await
+                } else {
+                    time::timeout_at(deadline, self.socket.recv_from(buf)).await
+                };
+
+                match answer {
+                    Ok(Ok((nbytes, src))) if self.is_server(src) => return Ok((nbytes, src)),
+                    Ok(Ok((_, src))) => {
+                        if peek {
+                            let _ = self.socket.recv_from(buf).await?;
+                        }
+                        let _ = self
+                            .socket
+                            .send_to(&machine::unknown_tid(src)[..], src)
+                            .await;
+                    }
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_) => break,
+                }
+            }
+
+            if retries == 0 {
+                return Err(Error::Timeout);
+            }
+            retries -= 1;
+        }
+    }
+
+    /// Whether `src` is on the host the requests are sent to.
+    fn is_server(&self, src: SocketAddr) -> bool {
+        self.server.iter().any(|server| server.ip() == src.ip())
+    }
+
+    async fn abort(&self, failure: Failure, server: SocketAddr) -> Error {
+        if let Some(ref packet) = failure.transmit {
+            let _ = self.socket.send_to(&packet[..], server).await;
+        }
+        failure.error
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UdpSocket;
+
+use crate::machine::{self, Failure, Receiver, Sender};
+use crate::packet::MAX_PACKET_SIZE;
+use crate::{Error, Result};
+
+/// Drives the state machine of a transfer over a tokio `UdpSocket`.
+pub struct AsyncConnection {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl AsyncConnection {
+    /// Creates a connection with the peer at `peer`, which is the Transfer ID
+    /// every packet of the transfer is expected to come from.
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        Self { socket, peer }
+    }
+
+    /// Receives a file, writing it out as it arrives.
+    pub async fn get<W: AsyncWrite + Unpin>(
+        &self,
+        mut receiver: Receiver,
+        mut writer: W,
+    ) -> Result<W> {
+        /* one extra byte so that oversized blocks are noticed */
+        let mut buf = vec![0; MAX_PACKET_SIZE + 1];
+        let mut output = receiver.start(Instant::now());
+
+        loop {
+            // Nothing is acknowledged before it has been written out.
+            if let Some(data) = output.data.take() {
+                if let Err(err) = writer.write_all(&buf[data]).await {
+                    return Err(self.abort(Failure::local(err)).await);
+                }
+            }
+
+            for packet in output.transmit {
+                let _ = self.send(packet).await?;
+            }
+
+            let deadline = match output.deadline {
+                Some(deadline) => deadline,
+                None => break,
+            };
+
+            let result = match self.recv_until(&mut buf, deadline).await? {
+                Some(bytes_recvd) => receiver.recv(&buf[..bytes_recvd], Instant::now()),
+                None => receiver.timeout(Instant::now()),
+            };
+            output = match result {
+                Ok(output) => output,
+                Err(failure) => return Err(self.abort(failure).await),
+            };
+        }
+
+        if let Err(err) = writer.flush().await {
+            return Err(self.abort(Failure::local(err)).await);
+        }
+
+        Ok(writer)
+    }
+
+    /// Sends a file, reading it in as the receiver makes room for it.
+    pub async fn put<R: AsyncRead + Unpin>(&self, mut sender: Sender, mut reader: R) -> Result<()> {
+        let mut block = vec![0; sender.block_size()];
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut output = sender.start(Instant::now());
+
+        loop {
+            for packet in output.transmit {
+                let _ = self.send(packet).await?;
+            }
+            // The output borrows from the sender, which is about to be fed again.
+            let deadline = output.deadline;
+
+            if sender.wants_block() {
+                let bytes_read = match read_block(&mut reader, &mut block).await {
+                    Ok(bytes_read) => bytes_read,
+                    Err(err) => return Err(self.abort(Failure::local(err)).await),
+                };
+
+                output = sender.push_block(&block[..bytes_read], Instant::now());
+                continue;
+            }
+
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => break,
+            };
+
+            let result = match self.recv_until(&mut buf, deadline).await? {
+                Some(bytes_recvd) => sender.recv(&buf[..bytes_recvd], Instant::now()),
+                None => sender.timeout(Instant::now()),
+            };
+            output = match result {
+                Ok(output) => output,
+                Err(failure) => return Err(self.abort(failure).await),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Tells the peer that the transfer failed, if it is to be told, and
+    /// returns the error to report locally.
+    pub async fn abort(&self, failure: Failure) -> Error {
+        if let Some(ref packet) = failure.transmit {
+            let _ = self.send(packet).await;
+        }
+        failure.error
+    }
+
+    async fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        self.socket.send_to(packet, self.peer).await
+    }
+
+    /// Waits for the next packet from the peer, or returns `None` once the
+    /// deadline passes.
+    ///
+    /// Packets from any other Transfer ID are answered with an error and
+    /// otherwise ignored, so that they do not disturb the transfer (RFC 1350
+    /// section 4).
+    async fn recv_until(&self, buf: &mut [u8], deadline: Instant) -> Result<Option<usize>> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+
+        loop {
+            let received = tokio::time::timeout_at(deadline, self.socket.recv_from(buf)).await;
+
+            match received {
+                Ok(Ok((bytes_recvd, src))) if src == self.peer => return Ok(Some(bytes_recvd)),
+                Ok(Ok((_, src))) => {
+                    let _ = self
+                        .socket
+                        .send_to(&machine::unknown_tid(src)[..], src)
+                        .await;
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Fills `buf` from `reader`, stopping short only at the end of the input.
+///
+/// A short block marks the end of a transfer, so a reader that returns less
+/// than was asked for must not end it prematurely.
+async fn read_block<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
@@ -0,0 +1,26 @@
+//! A client and server for use on a tokio runtime, enabled by the `async`
+//! feature.
+//!
+//! They speak the same protocol as `Client` and `Server`, and are configured
+//! the same way, but every transfer is a future rather than a blocking call.
+//! A server can thus handle many clients at once by spawning a task for each
+//! of them:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! let server = tftp::Server::new("0.0.0.0:6655", "./artifacts")?.into_async()?;
+//!
+//! loop {
+//!     let handler = server.serve().await?;
+//!     tokio::spawn(handler.handle());
+//! }
+//! # }
+//! ```
+
+pub use client::AsyncClient;
+pub use server::{AsyncHandler, AsyncServer};
+
+mod blocking;
+mod client;
+mod connection;
+mod server;
@@ -0,0 +1,215 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+use super::blocking::Blocking;
+use super::connection::AsyncConnection;
+use crate::access::{AccessPolicy, Direction};
+use crate::machine::{self, Failure, Request};
+use crate::negotiate::{Limits, TransferOptions};
+use crate::netascii;
+use crate::packet::*;
+use crate::server::{authorize, request_path, OverwriteRules};
+use crate::storage::Storage;
+use crate::Result;
+
+/// A TFTP server, like `Server`, but for use on a tokio runtime.
+///
+/// An `AsyncServer` is configured like a `Server`; see `Server::into_async`.
+pub struct AsyncServer {
+    socket: UdpSocket,
+    storage: Arc<dyn Storage>,
+    policy: Arc<dyn AccessPolicy>,
+    overwrite: Arc<OverwriteRules>,
+    transfer: TransferOptions,
+    limits: Limits,
+}
+
+impl AsyncServer {
+    pub(crate) fn new(
+        socket: UdpSocket,
+        storage: Arc<dyn Storage>,
+        policy: Arc<dyn AccessPolicy>,
+        overwrite: Arc<OverwriteRules>,
+        transfer: TransferOptions,
+        limits: Limits,
+    ) -> Self {
+        Self {
+            socket,
+            storage,
+            policy,
+            overwrite,
+            transfer,
+            limits,
+        }
+    }
+
+    /// Waits for requests and returns an `AsyncHandler` instance.
+    ///
+    /// It is intended that implementors will loop on this method and spawn
+    /// a task for every `AsyncHandler`, so that a single runtime can serve
+    /// many clients at once.
+    pub async fn serve(&self) -> Result<AsyncHandler> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, src_addr) = self.socket.recv_from(&mut buf).await?;
+
+        let request = match Request::parse(&buf[..nbytes], self.transfer.strictness) {
+            Ok(request) => request,
+            Err(failure) => {
+                if let Some(ref packet) = failure.transmit {
+                    let _ = self.socket.send_to(&packet[..], src_addr).await;
+                }
+                return Err(failure.error);
+            }
+        };
+
+        // Every transfer gets a port of its own, which is left to the
+        // operating system so that concurrent transfers never collide.
+        let ip = self.socket.local_addr()?.ip();
+        let socket = UdpSocket::bind((ip, 0)).await?;
+
+        Ok(AsyncHandler {
+            conn: AsyncConnection::new(socket, src_addr),
+            client: src_addr,
+            request,
+            storage: Arc::clone(&self.storage),
+            policy: Arc::clone(&self.policy),
+            overwrite: Arc::clone(&self.overwrite),
+            transfer: self.transfer,
+            limits: self.limits,
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+}
+
+/// Handles a request from a single TFTP client.
+pub struct AsyncHandler {
+    conn: AsyncConnection,
+    client: SocketAddr,
+    request: Request,
+    storage: Arc<dyn Storage>,
+    policy: Arc<dyn AccessPolicy>,
+    overwrite: Arc<OverwriteRules>,
+    transfer: TransferOptions,
+    limits: Limits,
+}
+
+impl AsyncHandler {
+    /// Completes the handshake with the client and services the request.
+    pub async fn handle(self) -> Result<()> {
+        match self.request {
+            Request::Read(ref rrq) => self.get(rrq).await,
+            Request::Write(ref wrq) => self.put(wrq).await,
+        }
+    }
+
+    async fn get(&self, rrq: &Packet<Rrq>) -> Result<()> {
+        let name = match request_path(&rrq.body.0) {
+            Ok(name) => name,
+            Err(failure) => return Err(self.conn.abort(failure).await),
+        };
+        let access = authorize(
+            &*self.policy,
+            self.client,
+            &name,
+            &rrq.body.0,
+            Direction::Read,
+        );
+        if let Err(failure) = access {
+            return Err(self.conn.abort(failure).await);
+        }
+
+        let (storage, client) = (Arc::clone(&self.storage), self.client);
+        let file = match unblock(move || storage.open_read(&name, client)).await {
+            Ok(file) => file,
+            Err(e) => return Err(self.conn.abort(Failure::local(e)).await),
+        };
+
+        let file_size = file.size();
+        let sender = match machine::accept_read(rrq, file_size, self.transfer, &self.limits) {
+            Ok(sender) => sender,
+            Err(failure) => return Err(self.conn.abort(failure).await),
+        };
+
+        let file = Blocking::new(file);
+        match rrq.body.0.mode {
+            Mode::NetAscii => self.conn.put(sender, netascii::Encoder::new(file)).await,
+            _ => self.conn.put(sender, file).await,
+        }
+    }
+
+    async fn put(&self, wrq: &Packet<Wrq>) -> Result<()> {
+        let name = match request_path(&wrq.body.0) {
+            Ok(name) => name,
+            Err(failure) => return Err(self.conn.abort(failure).await),
+        };
+        let access = authorize(
+            &*self.policy,
+            self.client,
+            &name,
+            &wrq.body.0,
+            Direction::Write,
+        );
+        if let Err(failure) = access {
+            return Err(self.conn.abort(failure).await);
+        }
+
+        // Negotiate first, so that a file too large to accept is never
+        // created.
+        let receiver = match machine::accept_write(wrq, self.transfer, &self.limits) {
+            Ok(receiver) => receiver,
+            Err(failure) => return Err(self.conn.abort(failure).await),
+        };
+
+        let (storage, client) = (Arc::clone(&self.storage), self.client);
+        let overwrite = self.overwrite.get(&name);
+        let file = match unblock(move || storage.open_write(&name, client, overwrite)).await {
+            Ok(file) => file,
+            Err(e) => return Err(self.conn.abort(Failure::local(e)).await),
+        };
+
+        let mut file = Blocking::new(file);
+        let received = match wrq.body.0.mode {
+            Mode::NetAscii => match self
+                .conn
+                .get(receiver, netascii::Decoder::new(&mut file))
+                .await
+            {
+                Ok(decoder) => Ok(decoder.finish_async().await.map(drop)?),
+                Err(err) => Err(err),
+            },
+            _ => self.conn.get(receiver, &mut file).await.map(drop),
+        };
+        let file = file.into_inner().await?;
+
+        // Only a file that arrived in full is kept.
+        match received {
+            Ok(()) => Ok(unblock(move || file.commit()).await?),
+            Err(err) => {
+                unblock(move || {
+                    file.abort();
+                    Ok(())
+                })
+                .await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Runs a blocking call of a `Storage` on tokio's blocking thread pool.
+async fn unblock<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
@@ -1,12 +1,12 @@
 use std::convert::AsRef;
-use std::ffi::{CStr, CString};
-use std::io::{self, ErrorKind};
 use std::mem::size_of;
 
+use crate::{Error, Result};
+
 pub trait FromBytes: Sized {
     type Error;
 
-    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self, Self::Error>;
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> std::result::Result<Self, Self::Error>;
 }
 
 pub trait IntoBytes {
@@ -26,17 +26,20 @@ impl<T> Bytes<T> {
 }
 
 impl FromBytes for Bytes<u16> {
-    type Error = io::Error;
+    type Error = Error;
 
-    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> io::Result<Self> {
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
 
+        if bytes.len() < size_of::<u16>() {
+            return Err(Error::parse(bytes.len(), "truncated packet"));
+        }
         if bytes.len() > size_of::<u16>() {
-            return Err(ErrorKind::InvalidInput.into());
+            return Err(Error::parse(size_of::<u16>(), "trailing bytes"));
         }
 
         let mut bs = [0u8; size_of::<u16>()];
-        bs.copy_from_slice(&bytes[..]);
+        bs.copy_from_slice(bytes);
         let be = u16::from_be_bytes(bs);
 
         Ok(Self(be))
@@ -50,25 +53,44 @@ impl IntoBytes for Bytes<u16> {
     }
 }
 
-impl FromBytes for Bytes<String> {
-    type Error = io::Error;
+/// A nul-terminated string of bytes, which need not be valid UTF-8.
+impl FromBytes for Bytes<Vec<u8>> {
+    type Error = Error;
 
-    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> io::Result<Self> {
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
-        let cstr = CStr::from_bytes_with_nul(bytes)
-            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
-        let s = cstr
-            .to_str()
-            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
 
-        Ok(Self(s.to_string()))
+        match bytes.first_nul_idx() {
+            Some(nul) if nul + 1 == bytes.len() => Ok(Self(bytes[..nul].to_vec())),
+            Some(nul) => Err(Error::parse(nul + 1, "trailing bytes")),
+            None => Err(Error::parse(bytes.len(), "missing nul terminator")),
+        }
+    }
+}
+
+impl IntoBytes for Bytes<Vec<u8>> {
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.0;
+        bytes.push(b'\0');
+        bytes
+    }
+}
+
+impl FromBytes for Bytes<String> {
+    type Error = Error;
+
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
+        let bytes = Bytes::<Vec<u8>>::from_bytes(bytes)?.into_inner();
+        let s = String::from_utf8(bytes)
+            .map_err(|e| Error::parse(e.utf8_error().valid_up_to(), "invalid UTF-8"))?;
+
+        Ok(Self(s))
     }
 }
 
 impl IntoBytes for Bytes<String> {
     fn into_bytes(self) -> Vec<u8> {
-        let c = CString::new(self.0).unwrap();
-        c.into_bytes_with_nul()
+        Bytes::new(self.0.into_bytes()).into_bytes()
     }
 }
 
@@ -108,6 +130,10 @@ mod tests {
         let n = 55u16;
         let actual = Bytes::from_bytes(&n.to_be_bytes()[..]).unwrap();
         assert_eq!(n, actual.into_inner());
+
+        assert!(Bytes::<u16>::from_bytes([]).is_err());
+        assert!(Bytes::<u16>::from_bytes([1]).is_err());
+        assert!(Bytes::<u16>::from_bytes([1, 2, 3]).is_err());
     }
 
     #[test]
@@ -122,6 +148,13 @@ mod tests {
         let b: Bytes<String> = Bytes::from_bytes(b"hello, world!\0").unwrap();
         let actual = b.into_inner();
         assert_eq!("hello, world!", actual.as_str());
+
+        let b = Bytes::<String>::from_bytes(b"hello");
+        assert!(matches!(b, Err(Error::Parse { offset: 5, .. })));
+        let b = Bytes::<String>::from_bytes(b"hello\0world\0");
+        assert!(matches!(b, Err(Error::Parse { offset: 6, .. })));
+        let b = Bytes::<String>::from_bytes(b"h\xffllo\0");
+        assert!(matches!(b, Err(Error::Parse { offset: 1, .. })));
     }
 
     #[test]
@@ -130,4 +163,16 @@ mod tests {
         let actual = b.into_bytes();
         assert_eq!(b"hello, world!\0", &actual[..]);
     }
+
+    #[test]
+    fn test_bytes_vec() {
+        let b = Bytes::<Vec<u8>>::from_bytes(b"caf\xe9\0").unwrap();
+        assert_eq!(&b.into_inner()[..], b"caf\xe9");
+
+        let b = Bytes::<Vec<u8>>::from_bytes(b"hello\0world\0");
+        assert!(matches!(b, Err(Error::Parse { offset: 6, .. })));
+
+        let b = Bytes::new(b"caf\xe9".to_vec());
+        assert_eq!(&b.into_bytes()[..], b"caf\xe9\0");
+    }
 }
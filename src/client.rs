@@ -1,16 +1,23 @@
 //! A client-side connection to a TFTP server. Implementors can use this
 //! to build a more fully-featured client application.
 
-use std::io::{self, Read, Result, Write};
+use std::io::{Read, Write};
 use std::iter::Iterator;
 use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
 
 use rand::Rng;
 
-use crate::bytes::{FromBytes, IntoBytes};
-use crate::connection::Connection;
+#[cfg(feature = "async")]
+use crate::asynchronous::AsyncClient;
+use crate::bytes::IntoBytes;
 use crate::connection::MIN_PORT_NUMBER;
+use crate::connection::{is_timeout, Connection};
+use crate::machine::{self, Failure, Receiver, Sender};
+use crate::negotiate::{self, TransferOptions};
+use crate::netascii;
 use crate::packet::*;
+use crate::{Error, Result};
 
 /// The initial state for building a `Client`.
 pub struct New {
@@ -24,6 +31,8 @@ pub struct New {
 pub struct ConnectTo {
     server: Vec<SocketAddr>,
     socket: UdpSocket,
+    options: Options,
+    transfer: TransferOptions,
 }
 
 /// Builds a `Client`.
@@ -35,6 +44,8 @@ pub struct Builder<T> {
 pub struct Client {
     server: Vec<SocketAddr>,
     socket: UdpSocket,
+    options: Options,
+    transfer: TransferOptions,
 }
 
 impl Builder<New> {
@@ -57,6 +68,8 @@ impl Builder<New> {
         let data = ConnectTo {
             server: resolved,
             socket: self.data.socket,
+            options: Options::new(),
+            transfer: TransferOptions::default(),
         };
 
         Ok(Builder { data })
@@ -64,20 +77,109 @@ impl Builder<New> {
 }
 
 impl Builder<ConnectTo> {
+    /// Requests an option (RFC 2347) with every transfer. The server is
+    /// free to ignore it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` contains a nul byte.
+    pub fn option<N: AsRef<str>, V: AsRef<str>>(mut self, name: N, value: V) -> Self {
+        self.data.options.insert(name, value);
+        self
+    }
+
+    /// Requests an option like `option`, or fails if `name` or `value`
+    /// contains a nul byte.
+    pub fn try_option<N: AsRef<str>, V: AsRef<str>>(mut self, name: N, value: V) -> Result<Self> {
+        self.data.options.try_insert(name, value)?;
+        Ok(self)
+    }
+
+    /// Requests a block size (RFC 2348) other than the default of 512 bytes.
+    ///
+    /// The server may choose a smaller block size. Valid sizes range from 8
+    /// to 65464 bytes.
+    pub fn block_size(self, size: usize) -> Self {
+        self.option(negotiate::BLKSIZE, size.to_string())
+    }
+
+    /// Requests that up to `size` blocks be sent before waiting for an
+    /// acknowledgement (RFC 7440), rather than one at a time.
+    ///
+    /// The server may choose a smaller window.
+    pub fn window_size(self, size: u16) -> Self {
+        self.option(negotiate::WINDOWSIZE, size.max(1).to_string())
+    }
+
+    /// Waits this long for the server before retransmitting, and asks the
+    /// server (RFC 2349) to do the same. The default is one second.
+    ///
+    /// The timeout is rounded up to whole seconds, from 1 to 255.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        let mut secs = timeout.as_secs();
+        if timeout.subsec_nanos() > 0 {
+            secs += 1;
+        }
+        let secs = secs.clamp(1, 255);
+
+        self.data.transfer.timeout = Duration::from_secs(secs);
+        self.option(negotiate::TIMEOUT, secs.to_string())
+    }
+
+    /// Asks the server to wrap block numbers around to `rollover` in files
+    /// of more than 65535 blocks, with the `rollover` option. Unless the
+    /// server acknowledges the option, block numbers wrap around to 0.
+    pub fn rollover(self, rollover: Rollover) -> Self {
+        let value = match rollover {
+            Rollover::Zero => "0",
+            Rollover::One => "1",
+        };
+        self.option(negotiate::ROLLOVER, value)
+    }
+
+    /// Retransmits a packet at most this many times in a row before giving
+    /// up on the server. The default is 5.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.data.transfer.retries = retries;
+        self
+    }
+
     /// Constructs the client.
     pub fn build(self) -> Client {
         Client {
             server: self.data.server,
             socket: self.data.socket,
+            options: self.data.options,
+            transfer: self.data.transfer,
         }
     }
 
+    /// Constructs a client for use on a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with I/O enabled.
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<AsyncClient> {
+        self.data.socket.set_nonblocking(true)?;
+        let socket = tokio::net::UdpSocket::from_std(self.data.socket)?;
+
+        Ok(AsyncClient::new(
+            self.data.server,
+            socket,
+            self.data.options,
+            self.data.transfer,
+        ))
+    }
+
     /// Creates an instance with a different socket from the origninal instance.
     pub fn try_clone(&self) -> Result<Self> {
         let new_sock_builder = Builder::new()?;
         let data = ConnectTo {
             server: self.data.server.clone(),
             socket: new_sock_builder.data.socket,
+            options: self.data.options.clone(),
+            transfer: self.data.transfer,
         };
         Ok(Builder { data })
     }
@@ -85,40 +187,329 @@ impl Builder<ConnectTo> {
 
 impl Client {
     /// Retrieves a file from the remote server.
-    pub fn get<S: AsRef<str>, W: Write>(self, file: S, mode: Mode, writer: W) -> Result<W> {
-        let rrq = Packet::rrq(file, mode);
-        let _ = self
-            .socket
-            .send_to(&rrq.into_bytes()[..], &self.server[..])?;
+    ///
+    /// The filename is sent as it is, so it need not be valid UTF-8, but a
+    /// filename that contains a nul byte is refused.
+    ///
+    /// In `NetAscii` mode, line endings are translated as the file is
+    /// received.
+    pub fn get<S: AsRef<[u8]>, W: Write>(self, file: S, mode: Mode, writer: W) -> Result<W> {
+        self.receive(file, mode, writer, |_, _| ())
+    }
 
-        let mut buf = [0; MAX_PACKET_SIZE];
-        let (_, server) = self.socket.peek_from(&mut buf)?;
-        self.socket.connect(server)?;
+    /// Retrieves a file from the remote server, like `get`, and asks the
+    /// server for its size (RFC 2349) along the way.
+    ///
+    /// `on_size` is called with the size the server announces, or `None` if
+    /// it does not support the option, before anything is written to
+    /// `writer`. This way buffers can be sized up front, and progress can be
+    /// reported against the size. In `NetAscii` mode, the size is that of
+    /// the file as it is sent, before line endings are translated.
+    pub fn get_with_size<S, W, F>(mut self, file: S, mode: Mode, writer: W, on_size: F) -> Result<W>
+    where
+        S: AsRef<[u8]>,
+        W: Write,
+        F: FnOnce(Option<u64>, &mut W),
+    {
+        self.options.insert(negotiate::TSIZE, "0");
+        self.receive(file, mode, writer, on_size)
+    }
 
-        let conn = Connection::new(self.socket);
-        conn.get(writer)
+    fn receive<S, W, F>(self, file: S, mode: Mode, mut writer: W, on_size: F) -> Result<W>
+    where
+        S: AsRef<[u8]>,
+        W: Write,
+        F: FnOnce(Option<u64>, &mut W),
+    {
+        // An `Oack` must be acknowledged before the server sends any data.
+        let (options, server) = self.request_read(file, mode)?;
+        on_size(
+            options.and_then(|options| options.transfer_size),
+            &mut writer,
+        );
+
+        let receiver = match options {
+            Some(options) => {
+                let ack = Packet::ack(Block::new(0));
+                Receiver::new(options, Some(ack.into_bytes()))
+            }
+            None => Receiver::new(self.transfer, None),
+        };
+
+        let conn = Connection::new(self.socket, server);
+        match mode {
+            Mode::NetAscii => Ok(conn
+                .get(receiver, netascii::Decoder::new(writer))?
+                .finish()?),
+            _ => conn.get(receiver, writer),
+        }
+    }
+
+    /// Asks the remote server for the size of a file (RFC 2349) without
+    /// retrieving it.
+    ///
+    /// Returns `None` if the server does not support the transfer size
+    /// option.
+    ///
+    /// This costs a round trip of its own, after which the server is told to
+    /// abandon the transfer. To learn the size of a file that is retrieved
+    /// anyway, use `get_with_size` instead.
+    pub fn size<S: AsRef<[u8]>>(mut self, file: S, mode: Mode) -> Result<Option<u64>> {
+        self.options.insert(negotiate::TSIZE, "0");
+
+        let (options, server) = self.request_read(file, mode)?;
+        let size = options.and_then(|options| options.transfer_size);
+
+        // Either way, the server is waiting for this transfer to proceed.
+        let error = Packet::error(Code::NotDefined, "transfer size probe complete");
+        let _ = self.socket.send_to(&error.into_bytes()[..], server);
+
+        Ok(size)
     }
 
     /// Stores a file on the remote server.
-    pub fn put<S: AsRef<str>, R: Read>(self, file: S, mode: Mode, reader: R) -> Result<()> {
-        let wrq = Packet::wrq(file, mode);
-        let _ = self
-            .socket
-            .send_to(&wrq.into_bytes()[..], &self.server[..])?;
-
-        let mut buf = [0; MAX_PACKET_SIZE];
-        let (nbytes, server) = self.socket.recv_from(&mut buf)?;
-        self.socket.connect(server)?;
-
-        let _ = match Packet::<Ack>::from_bytes(&buf[..nbytes]) {
-            Ok(a) => a,
-            Err(e) => {
-                let error: Packet<Error> = e.into();
-                return Err(io::Error::from(error));
+    ///
+    /// As with `get`, the filename need not be valid UTF-8.
+    ///
+    /// In `NetAscii` mode, line endings are translated as the file is sent.
+    pub fn put<S: AsRef<[u8]>, R: Read>(self, file: S, mode: Mode, reader: R) -> Result<()> {
+        let wrq = Packet::try_wrq(file, mode, self.options.clone())?;
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, server) = self.request(&wrq.into_bytes()[..], &mut buf, false)?;
+
+        let options = machine::answer_write(&buf[..nbytes], &self.options, self.transfer)
+            .map_err(|failure| self.abort(failure, server))?;
+
+        let sender = Sender::new(options, None);
+        let conn = Connection::new(self.socket, server);
+        match mode {
+            Mode::NetAscii => conn.put(sender, netascii::Encoder::new(reader)),
+            _ => conn.put(sender, reader),
+        }
+    }
+
+    /// Stores a file of a known size on the remote server, announcing its
+    /// size (RFC 2349) so that the server may refuse it up front.
+    pub fn put_with_size<S: AsRef<[u8]>, R: Read>(
+        mut self,
+        file: S,
+        mode: Mode,
+        reader: R,
+        size: u64,
+    ) -> Result<()> {
+        self.options.insert(negotiate::TSIZE, size.to_string());
+        self.put(file, mode, reader)
+    }
+
+    /// Sends a read request and waits for the server to answer it.
+    ///
+    /// Returns the negotiated options if the server answered with an `Oack`,
+    /// which is yet to be acknowledged. Otherwise the server's first `Data`
+    /// packet is left in the socket. Either way, the server's Transfer ID is
+    /// returned as well.
+    fn request_read<S: AsRef<[u8]>>(
+        &self,
+        file: S,
+        mode: Mode,
+    ) -> Result<(Option<TransferOptions>, SocketAddr)> {
+        let rrq = Packet::try_rrq(file, mode, self.options.clone())?;
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, server) = self.request(&rrq.into_bytes()[..], &mut buf, true)?;
+
+        let answer = machine::answer_read(&buf[..nbytes], &self.options, self.transfer);
+
+        // Only the first `Data` packet is left for the transfer to receive.
+        if !matches!(answer, Ok(None)) {
+            let _ = self.socket.recv(&mut buf)?;
+        }
+
+        let options = answer.map_err(|failure| self.abort(failure, server))?;
+        Ok((options, server))
+    }
+
+    /// Sends a request to the server, retransmitting it until the server
+    /// answers, and returns the Transfer ID the answer came from. The rest
+    /// of the transfer is expected to come from there as well.
+    ///
+    /// The server may answer from any port, but only from its own address.
+    /// Answers from any other host are refused with an error, so that no
+    /// one can pose as the server before its Transfer ID is known (RFC 1350
+    /// section 4).
+    ///
+    /// If `peek` is set, the answer is left in the socket.
+    fn request(&self, request: &[u8], buf: &mut [u8], peek: bool) -> Result<(usize, SocketAddr)> {
+        let mut retries = self.transfer.retries;
+        loop {
+            let _ = self.socket.send_to(request, &self.server[..])?;
+            let deadline = Instant::now() + self.transfer.timeout;
+
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(deadline - now))?;
+
+                let answer = if peek {
+                    self.socket.peek_from(buf)
+                } else {
+                    self.socket.recv_from(buf)
+                };
+
+                match answer {
+                    Ok((nbytes, src)) if self.is_server(src) => return Ok((nbytes, src)),
+                    Ok((_, src)) => {
+                        if peek {
+                            let _ = self.socket.recv_from(buf)?;
+                        }
+                        let _ = self.socket.send_to(&machine::unknown_tid(src)[..], src);
+                    }
+                    Err(e) if is_timeout(&e) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            if retries == 0 {
+                return Err(Error::Timeout);
             }
+            retries -= 1;
+        }
+    }
+
+    /// Whether `src` is on the host the requests are sent to.
+    fn is_server(&self, src: SocketAddr) -> bool {
+        self.server.iter().any(|server| server.ip() == src.ip())
+    }
+
+    /// Tells the server that the transfer failed, if it is to be told, and
+    /// returns the error to report locally.
+    fn abort(&self, failure: Failure, server: SocketAddr) -> Error {
+        if let Some(ref packet) = failure.transmit {
+            let _ = self.socket.send_to(&packet[..], server);
+        }
+        failure.error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::bytes::FromBytes;
+
+    #[test]
+    fn test_try_option_refuses_nul() {
+        let builder = Builder::new().unwrap().connect_to("127.0.0.1:69").unwrap();
+        let builder = builder.try_option("vendor", "a").unwrap();
+        assert!(matches!(
+            builder.try_option("vendor", "a\0b"),
+            Err(Error::InvalidOption)
+        ));
+    }
+
+    #[test]
+    fn test_request_is_retransmitted() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut builder = Builder::new()
+            .unwrap()
+            .connect_to(server.local_addr().unwrap())
+            .unwrap();
+        builder.data.transfer.timeout = Duration::from_millis(50);
+        let client = builder.build();
+
+        let client_thread = thread::spawn(move || client.get("file", Mode::Octet, Vec::new()));
+
+        // The first request is lost.
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, _) = server.recv_from(&mut buf).unwrap();
+        let first = Packet::<Rrq>::from_bytes(&buf[..nbytes]).unwrap();
+        let (nbytes, client_addr) = server.recv_from(&mut buf).unwrap();
+        let second = Packet::<Rrq>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(first, second);
+
+        let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        transfer.connect(client_addr).unwrap();
+        let data = Packet::data(Block::new(1), b"a");
+        transfer.send(&data.into_bytes()[..]).unwrap();
+
+        let nbytes = transfer.recv(&mut buf).unwrap();
+        let ack = Packet::<Ack>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(ack.body.block, Block::new(1));
+
+        assert_eq!(client_thread.join().unwrap().unwrap(), b"a");
+    }
+
+    #[test]
+    fn test_get_translates_netascii() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = Builder::new()
+            .unwrap()
+            .connect_to(server.local_addr().unwrap())
+            .unwrap()
+            .build();
+
+        let client_thread = thread::spawn(move || client.get("file", Mode::NetAscii, Vec::new()));
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (nbytes, client_addr) = server.recv_from(&mut buf).unwrap();
+        let rrq = Packet::<Rrq>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(rrq.body.0.mode, Mode::NetAscii);
+
+        let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        transfer.connect(client_addr).unwrap();
+        let data = Packet::data(Block::new(1), b"a\r\nb\r\x00c\r");
+        transfer.send(&data.into_bytes()[..]).unwrap();
+
+        let nbytes = transfer.recv(&mut buf).unwrap();
+        let ack = Packet::<Ack>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(ack.body.block, Block::new(1));
+
+        assert_eq!(client_thread.join().unwrap().unwrap(), b"a\nb\rc\r");
+    }
+
+    #[test]
+    fn test_request_refuses_strangers() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        // Any address of 127.0.0.0/8 is on the loopback interface on Linux,
+        // but elsewhere there may be no other address to answer from.
+        let stranger = match UdpSocket::bind("127.0.0.2:0") {
+            Ok(stranger) => stranger,
+            Err(_) => return,
         };
 
-        let conn = Connection::new(self.socket);
-        conn.put(reader)
+        let client = Builder::new()
+            .unwrap()
+            .connect_to(server.local_addr().unwrap())
+            .unwrap()
+            .build();
+
+        let client_thread = thread::spawn(move || client.get("file", Mode::Octet, Vec::new()));
+
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let (_, client_addr) = server.recv_from(&mut buf).unwrap();
+
+        // A stranger answers before the server does.
+        let data = Packet::data(Block::new(1), b"forged");
+        stranger
+            .send_to(&data.into_bytes()[..], client_addr)
+            .unwrap();
+        let nbytes = stranger.recv(&mut buf).unwrap();
+        let error = Packet::<crate::packet::Error>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(error.body.code, Code::UnknownTid);
+
+        let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        transfer.connect(client_addr).unwrap();
+        let data = Packet::data(Block::new(1), b"genuine");
+        transfer.send(&data.into_bytes()[..]).unwrap();
+
+        let nbytes = transfer.recv(&mut buf).unwrap();
+        let ack = Packet::<Ack>::from_bytes(&buf[..nbytes]).unwrap();
+        assert_eq!(ack.body.block, Block::new(1));
+
+        assert_eq!(client_thread.join().unwrap().unwrap(), b"genuine");
     }
 }
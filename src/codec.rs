@@ -0,0 +1,94 @@
+//! A codec for TFTP packets, enabled by the `codec` feature.
+//!
+//! `TftpCodec` turns datagrams into `AnyPacket`s and back, so that a
+//! `tokio_util::udp::UdpFramed` socket can be used to build tools that speak
+//! TFTP, such as relays and monitors:
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use tokio::net::UdpSocket;
+//! use tokio_util::udp::UdpFramed;
+//!
+//! use tftp::codec::TftpCodec;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let socket = UdpSocket::bind("0.0.0.0:69").await?;
+//! let mut framed = UdpFramed::new(socket, TftpCodec);
+//!
+//! while let Some(received) = framed.next().await {
+//!     match received {
+//!         Ok((packet, src)) => println!("{} from {}", packet, src),
+//!         Err(err) => println!("garbage: {}", err),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use tokio_util::bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::packet::AnyPacket;
+use crate::{Error, Result};
+
+/// Decodes and encodes TFTP packets, one per datagram.
+///
+/// A datagram with an unknown opcode is decoded as `AnyPacket::Raw`. One that
+/// is not a valid packet is discarded as a whole and reported as an error,
+/// after which decoding carries on with the next one.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TftpCodec;
+
+impl Decoder for TftpCodec {
+    type Item = AnyPacket;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<AnyPacket>> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+
+        // A packet is never split across datagrams, so the whole buffer is
+        // taken before parsing. A datagram that fails to parse must not be
+        // seen again.
+        let datagram = src.split();
+        AnyPacket::from_bytes(&datagram[..]).map(Some)
+    }
+}
+
+impl Encoder<AnyPacket> for TftpCodec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: AnyPacket, dst: &mut BytesMut) -> Result<()> {
+        dst.extend_from_slice(&packet.into_bytes()[..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::IntoBytes;
+    use crate::packet::{Block, Packet};
+
+    #[test]
+    fn test_decode() {
+        let mut src = BytesMut::from(&Packet::ack(Block::new(7)).into_bytes()[..]);
+        let packet = TftpCodec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(packet, Packet::ack(Block::new(7)).into());
+        assert_eq!(TftpCodec.decode(&mut src).unwrap(), None);
+
+        // Garbage is dropped along with the error.
+        let mut src = BytesMut::from(&b"\x00\x04garbage"[..]);
+        assert!(TftpCodec.decode(&mut src).is_err());
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_encode() {
+        let packet = Packet::data(Block::new(1), b"hello");
+        let mut dst = BytesMut::new();
+        TftpCodec.encode(packet.clone().into(), &mut dst).unwrap();
+        assert_eq!(&dst[..], &packet.into_bytes()[..]);
+    }
+}
@@ -1,95 +1,172 @@
-use std::io::{Read, Result, Write};
-use std::net::UdpSocket;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
 
-use crate::bytes::IntoBytes;
-use crate::packet::expect::ExpectPacket;
-use crate::packet::*;
-
-/*
- * TODO: Probably add support for timeouts and retransmissions */
+use crate::machine::{self, Failure, Receiver, Sender};
+use crate::packet::MAX_PACKET_SIZE;
+use crate::{Error, Result};
 
 pub const MIN_PORT_NUMBER: u16 = 1001;
 
+/// Drives the state machine of a transfer over a blocking `UdpSocket`.
 pub struct Connection {
     socket: UdpSocket,
+    peer: SocketAddr,
 }
 
 impl Connection {
-    pub fn new(socket: UdpSocket) -> Self {
-        Self { socket }
+    /// Creates a connection with the peer at `peer`, which is the Transfer ID
+    /// every packet of the transfer is expected to come from.
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        Self { socket, peer }
     }
 
-    pub fn get<W: Write>(self, mut writer: W) -> Result<W> {
-        loop {
-            let mut buf = [0; MAX_PACKET_SIZE];
-            let bytes_recvd = self.socket.recv(&mut buf)?;
+    /// Receives a file, writing it out as it arrives.
+    pub fn get<W: Write>(&self, mut receiver: Receiver, mut writer: W) -> Result<W> {
+        /* one extra byte so that oversized blocks are noticed */
+        let mut buf = vec![0; MAX_PACKET_SIZE + 1];
+        let mut output = receiver.start(Instant::now());
 
-            let data: Packet<Data> = self.socket.expect_packet(&buf[..bytes_recvd])?;
+        loop {
+            // Nothing is acknowledged before it has been written out.
+            if let Some(data) = output.data.take() {
+                if let Err(err) = writer.write_all(&buf[data]) {
+                    return Err(self.abort(Failure::local(err)));
+                }
+            }
 
-            if let Err(err) = writer.write_all(&data.body.data[..]) {
-                let _ = self
-                    .socket
-                    .send(&Packet::error(err.kind().into(), format!("{}", err)).into_bytes()[..]);
-                return Err(err);
+            for packet in output.transmit {
+                let _ = self.send(packet)?;
             }
 
-            let ack = Packet::ack(data.body.block);
-            let _ = self.socket.send(&ack.into_bytes()[..])?;
+            let deadline = match output.deadline {
+                Some(deadline) => deadline,
+                None => break,
+            };
 
-            if data.body.data.len() < MAX_PAYLOAD_SIZE {
-                break;
-            }
+            let result = match self.recv_until(&mut buf, deadline)? {
+                Some(bytes_recvd) => receiver.recv(&buf[..bytes_recvd], Instant::now()),
+                None => receiver.timeout(Instant::now()),
+            };
+            output = result.map_err(|failure| self.abort(failure))?;
         }
 
         Ok(writer)
     }
 
-    pub fn put<R: Read>(self, mut reader: R) -> Result<()> {
-        let mut current_block = 1;
+    /// Sends a file, reading it in as the receiver makes room for it.
+    pub fn put<R: Read>(&self, mut sender: Sender, mut reader: R) -> Result<()> {
+        let mut block = vec![0; sender.block_size()];
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let mut output = sender.start(Instant::now());
 
         loop {
-            let mut buf = [0; MAX_PAYLOAD_SIZE];
-
-            let bytes_read = match reader.read(&mut buf) {
-                Ok(bytes_read) => bytes_read,
-                Err(err) => {
-                    let _ = self.socket.send(
-                        &Packet::error(err.kind().into(), format!("{}", err)).into_bytes()[..],
-                    );
-                    return Err(err);
-                }
+            for packet in output.transmit {
+                let _ = self.send(packet)?;
+            }
+            // The output borrows from the sender, which is about to be fed again.
+            let deadline = output.deadline;
+
+            if sender.wants_block() {
+                let bytes_read = match read_block(&mut reader, &mut block) {
+                    Ok(bytes_read) => bytes_read,
+                    Err(err) => return Err(self.abort(Failure::local(err))),
+                };
+
+                output = sender.push_block(&block[..bytes_read], Instant::now());
+                continue;
+            }
+
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => break,
             };
 
-            let data = Packet::data(Block::new(current_block), buf[..bytes_read].to_vec());
+            let result = match self.recv_until(&mut buf, deadline)? {
+                Some(bytes_recvd) => sender.recv(&buf[..bytes_recvd], Instant::now()),
+                None => sender.timeout(Instant::now()),
+            };
+            output = result.map_err(|failure| self.abort(failure))?;
+        }
 
-            let _ = self.socket.send(&data.into_bytes()[..])?;
+        Ok(())
+    }
 
-            let mut buf = [0; MAX_PACKET_SIZE];
-            let bytes_recvd = self.socket.recv(&mut buf)?;
+    /// Tells the peer that the transfer failed, if it is to be told, and
+    /// returns the error to report locally.
+    pub fn abort(&self, failure: Failure) -> Error {
+        if let Some(ref packet) = failure.transmit {
+            let _ = self.send(packet);
+        }
+        failure.error
+    }
 
-            let ack: Packet<Ack> = self.socket.expect_packet(&buf[..bytes_recvd])?;
+    fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        self.socket.send_to(packet, self.peer)
+    }
 
-            assert_eq!(Block::new(current_block), ack.body.block);
-            current_block += 1;
+    /// Waits for the next packet from the peer, or returns `None` once the
+    /// deadline passes.
+    ///
+    /// Packets from any other Transfer ID are answered with an error and
+    /// otherwise ignored, so that they do not disturb the transfer (RFC 1350
+    /// section 4).
+    fn recv_until(&self, buf: &mut [u8], deadline: Instant) -> Result<Option<usize>> {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
 
-            if bytes_read < MAX_PAYLOAD_SIZE {
-                break;
+            match self.socket.recv_from(buf) {
+                Ok((bytes_recvd, src)) if src == self.peer => return Ok(Some(bytes_recvd)),
+                Ok((_, src)) => {
+                    let _ = self.socket.send_to(&machine::unknown_tid(src)[..], src);
+                }
+                Err(e) if is_timeout(&e) => return Ok(None),
+                Err(e) => return Err(e.into()),
             }
         }
+    }
+}
 
-        Ok(())
+/// Reading from a socket that has a timeout fails with either of these kinds,
+/// depending on the platform.
+pub fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut
+}
+
+/// Fills `buf` from `reader`, stopping short only at the end of the input.
+///
+/// A short block marks the end of a transfer, so a reader that returns less
+/// than was asked for must not end it prematurely.
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
     }
+
+    Ok(filled)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io;
+    use std::time::Duration;
 
     use rand::Rng;
 
     use super::*;
-    use crate::bytes::FromBytes;
-    use crate::packet::{Code, Error};
+    use crate::bytes::{FromBytes, IntoBytes};
+    use crate::negotiate::TransferOptions;
+    use crate::packet::{self, *};
+    use crate::Error;
 
     fn test_blank_sends_invalid_packet_error<T, F>(f: F)
     where
@@ -110,7 +187,8 @@ mod tests {
         client_sock.connect(("localhost", server_port)).unwrap();
 
         // Create a connection struct for our client
-        let client_conn = Connection::new(client_sock);
+        let server_addr = server_sock.local_addr().unwrap();
+        let client_conn = Connection::new(client_sock, server_addr);
 
         // Send an (hopefully) invalid packet
         server_sock
@@ -120,32 +198,217 @@ mod tests {
         // Assert that, when trying to <blank>, we get an invalid packet error
         // let err = client_conn.get(&mut Vec::new()).unwrap_err();
         let actual = f(client_conn).unwrap_err();
-        let expected: io::Error =
-            Packet::error(Code::IllegalOperation, Code::IllegalOperation.as_str()).into();
-        assert_eq!(actual.kind(), expected.kind());
+        assert!(matches!(
+            actual,
+            Error::Protocol {
+                code: Code::IllegalOperation,
+                ..
+            }
+        ));
 
         // Find the first error packet, assuring we skip over the data packet that gets sent in the put test
-        let mut buf = [0; MAX_PACKET_SIZE];
+        let mut buf = vec![0; MAX_PACKET_SIZE];
         let rcvd = loop {
             let (rcvd, _) = server_sock.recv_from(&mut buf).unwrap();
-            if let Ok(_) = Packet::<Data>::from_bytes(&buf[..rcvd]) {
+            if Packet::<Data>::from_bytes(&buf[..rcvd]).is_ok() {
                 continue;
             }
             break rcvd;
         };
 
         // Assert that we get an "invalid packet" error packet
-        let actual: io::Error = Packet::<Error>::from_bytes(&buf[..rcvd]).unwrap().into();
-        assert_eq!(actual.kind(), expected.kind());
+        let actual = Packet::<packet::Error>::from_bytes(&buf[..rcvd]).unwrap();
+        assert_eq!(actual.body.code, Code::IllegalOperation);
     }
 
     #[test]
     fn test_get_sends_invalid_packet_error() {
-        test_blank_sends_invalid_packet_error(|conn| conn.get(Vec::new()))
+        test_blank_sends_invalid_packet_error(|conn| {
+            let receiver = Receiver::new(TransferOptions::default(), None);
+            conn.get(receiver, Vec::new())
+        })
     }
 
     #[test]
     fn test_put_sends_invalid_packet_error() {
-        test_blank_sends_invalid_packet_error(|conn| conn.put(&b"wowzers"[..]))
+        test_blank_sends_invalid_packet_error(|conn| {
+            let sender = Sender::new(TransferOptions::default(), None);
+            conn.put(sender, &b"wowzers"[..])
+        })
+    }
+
+    /// Creates a `Connection` and the socket of the peer it talks to.
+    fn connection_with_peer() -> (Connection, UdpSocket) {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        peer.connect(socket.local_addr().unwrap()).unwrap();
+
+        let peer_addr = peer.local_addr().unwrap();
+        (Connection::new(socket, peer_addr), peer)
+    }
+
+    fn windowed(window_size: u16) -> TransferOptions {
+        TransferOptions {
+            block_size: 8,
+            window_size,
+            ..TransferOptions::default()
+        }
+    }
+
+    fn impatient(retries: u32) -> TransferOptions {
+        TransferOptions {
+            block_size: 8,
+            timeout: Duration::from_millis(50),
+            retries,
+            ..TransferOptions::default()
+        }
+    }
+
+    fn recv_packet<P: crate::packet::sealed::Packet>(socket: &UdpSocket) -> Packet<P> {
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+        let rcvd = socket.recv(&mut buf).unwrap();
+        Packet::<P>::from_bytes(&buf[..rcvd]).unwrap()
+    }
+
+    fn send_data(socket: &UdpSocket, block: u16, data: &[u8]) {
+        let data = Packet::data(Block::new(block), data);
+        socket.send(&data.into_bytes()[..]).unwrap();
+    }
+
+    #[test]
+    fn test_get_acks_once_per_window() {
+        let (conn, peer) = connection_with_peer();
+        let receiver = Receiver::new(windowed(2), None);
+        let receiver = std::thread::spawn(move || conn.get(receiver, Vec::new()));
+
+        send_data(&peer, 1, b"aaaaaaaa");
+        send_data(&peer, 2, b"bbbbbbbb");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(2));
+
+        send_data(&peer, 3, b"c");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(3));
+
+        assert_eq!(receiver.join().unwrap().unwrap(), b"aaaaaaaabbbbbbbbc");
+    }
+
+    #[test]
+    fn test_get_resends_ack_on_timeout() {
+        let (conn, peer) = connection_with_peer();
+        let receiver = Receiver::new(impatient(5), None);
+        let receiver = std::thread::spawn(move || conn.get(receiver, Vec::new()));
+
+        send_data(&peer, 1, b"aaaaaaaa");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(1));
+
+        // Block 2 was lost, so the acknowledgement for block 1 is repeated.
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(1));
+
+        send_data(&peer, 2, b"b");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(2));
+
+        assert_eq!(receiver.join().unwrap().unwrap(), b"aaaaaaaab");
+    }
+
+    #[test]
+    fn test_get_dallies_after_final_ack() {
+        let (conn, peer) = connection_with_peer();
+        let receiver = Receiver::new(impatient(5), None);
+        let receiver = std::thread::spawn(move || conn.get(receiver, Vec::new()));
+
+        send_data(&peer, 1, b"a");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(1));
+
+        // The final acknowledgement was lost.
+        send_data(&peer, 1, b"a");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(1));
+
+        assert_eq!(receiver.join().unwrap().unwrap(), b"a");
+    }
+
+    #[test]
+    fn test_put_resends_data_on_timeout() {
+        let (conn, peer) = connection_with_peer();
+        let sender = Sender::new(impatient(5), None);
+        let sender = std::thread::spawn(move || conn.put(sender, &b"aaaaaaaab"[..]));
+
+        assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(1));
+        assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(1));
+        peer.send(&Packet::ack(Block::new(1)).into_bytes()[..])
+            .unwrap();
+
+        assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(2));
+        peer.send(&Packet::ack(Block::new(2)).into_bytes()[..])
+            .unwrap();
+
+        sender.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_put_gives_up_after_retries() {
+        let (conn, peer) = connection_with_peer();
+        let sender = Sender::new(impatient(2), None);
+        let sender = std::thread::spawn(move || conn.put(sender, &b"a"[..]));
+
+        for _ in 0..3 {
+            assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(1));
+        }
+
+        let error = recv_packet::<packet::Error>(&peer);
+        assert_eq!(error.body.code, Code::NotDefined);
+
+        let error = sender.join().unwrap().unwrap_err();
+        assert!(matches!(error, Error::Timeout));
+    }
+
+    #[test]
+    fn test_get_answers_unknown_tid() {
+        let (conn, peer) = connection_with_peer();
+        let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+        stranger.connect(conn.socket.local_addr().unwrap()).unwrap();
+        let receiver = Receiver::new(windowed(1), None);
+        let receiver = std::thread::spawn(move || conn.get(receiver, Vec::new()));
+
+        send_data(&peer, 1, b"aaaaaaaa");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(1));
+
+        // A packet from another transfer is turned away and not written.
+        send_data(&stranger, 2, b"x");
+        assert_eq!(
+            recv_packet::<packet::Error>(&stranger).body.code,
+            Code::UnknownTid
+        );
+
+        send_data(&peer, 2, b"b");
+        assert_eq!(recv_packet::<Ack>(&peer).body.block, Block::new(2));
+
+        assert_eq!(receiver.join().unwrap().unwrap(), b"aaaaaaaab");
+    }
+
+    #[test]
+    fn test_put_answers_unknown_tid() {
+        let (conn, peer) = connection_with_peer();
+        let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+        stranger.connect(conn.socket.local_addr().unwrap()).unwrap();
+        let sender = Sender::new(windowed(1), None);
+        let sender = std::thread::spawn(move || conn.put(sender, &b"aaaaaaaab"[..]));
+
+        assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(1));
+
+        // An acknowledgement from another transfer does not move the window.
+        stranger
+            .send(&Packet::ack(Block::new(1)).into_bytes()[..])
+            .unwrap();
+        assert_eq!(
+            recv_packet::<packet::Error>(&stranger).body.code,
+            Code::UnknownTid
+        );
+
+        peer.send(&Packet::ack(Block::new(1)).into_bytes()[..])
+            .unwrap();
+        assert_eq!(recv_packet::<Data>(&peer).body.block, Block::new(2));
+        peer.send(&Packet::ack(Block::new(2)).into_bytes()[..])
+            .unwrap();
+
+        sender.join().unwrap().unwrap();
     }
 }
@@ -0,0 +1,234 @@
+//! The errors that TFTP transfers and packet parsing fail with.
+//!
+//! An `Error` tells apart a server that refused a request ("file not found")
+//! from a peer that went quiet or a local file that could not be written, so
+//! that callers can react to each of them differently.
+
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+
+use crate::packet::{self, Code, Packet};
+
+/// A specialized `Result` type for TFTP operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The ways in which a TFTP operation can fail.
+#[derive(Debug)]
+pub enum Error {
+    /// A packet could not be parsed.
+    Parse {
+        /// How far into the packet the problem lies.
+        offset: usize,
+
+        /// What is wrong with the packet.
+        reason: &'static str,
+    },
+
+    /// The peer ended the transfer with an error packet.
+    Remote {
+        /// The code the peer sent.
+        code: Code,
+
+        /// The message the peer sent.
+        message: String,
+    },
+
+    /// The peer stopped answering.
+    Timeout,
+
+    /// A packet arrived from a Transfer ID that takes no part in the
+    /// transfer (RFC 1350 section 4).
+    UnknownTid(SocketAddr),
+
+    /// A local file or socket failed.
+    Io(io::Error),
+
+    /// A filename contains a nul byte, which would end it early on the wire.
+    InvalidFilename,
+
+    /// An option name or value contains a nul byte, which would split it in
+    /// two on the wire.
+    InvalidOption,
+
+    /// The transfer was ended because the peer broke the protocol or asked
+    /// for something that cannot be granted.
+    Protocol {
+        /// The code the peer is told.
+        code: Code,
+
+        /// The message the peer is told.
+        message: String,
+    },
+}
+
+impl Error {
+    /// A parse error at `offset`.
+    pub(crate) fn parse(offset: usize, reason: &'static str) -> Self {
+        Error::Parse { offset, reason }
+    }
+
+    /// A protocol violation whose message is the description of its code.
+    pub(crate) fn protocol(code: Code) -> Self {
+        Error::Protocol {
+            code,
+            message: code.as_str().to_string(),
+        }
+    }
+
+    /// Moves the offset of a parse error along by `by` bytes, for errors in
+    /// a part of a packet that was parsed on its own.
+    pub(crate) fn offset_by(self, by: usize) -> Self {
+        match self {
+            Error::Parse { offset, reason } => Error::parse(offset + by, reason),
+            err => err,
+        }
+    }
+
+    /// The error code that describes this error to a peer.
+    pub fn code(&self) -> Code {
+        match self {
+            Error::Parse { .. } | Error::InvalidFilename | Error::InvalidOption => {
+                Code::IllegalOperation
+            }
+            Error::Remote { code, .. } | Error::Protocol { code, .. } => *code,
+            Error::Timeout => Code::NotDefined,
+            Error::UnknownTid(_) => Code::UnknownTid,
+            Error::Io(err) => err.kind().into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { offset, reason } => {
+                write!(f, "malformed packet at byte {}: {}", offset, reason)
+            }
+            Error::Remote { code, message } => write!(f, "peer error ({}): {}", code, message),
+            Error::Timeout => write!(f, "transfer timed out"),
+            Error::UnknownTid(addr) => write!(f, "packet from unknown transfer ID {}", addr),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::InvalidFilename => write!(f, "filename contains a nul byte"),
+            Error::InvalidOption => write!(f, "option contains a nul byte"),
+            Error::Protocol { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::Io(err) => return err,
+            Error::Remote { code, .. } => match code {
+                Code::FileNotFound => ErrorKind::NotFound,
+                Code::AccessViolation => ErrorKind::PermissionDenied,
+                Code::FileAlreadyExists => ErrorKind::AlreadyExists,
+                _ => ErrorKind::Other,
+            },
+            Error::Timeout => ErrorKind::TimedOut,
+            Error::InvalidFilename | Error::InvalidOption => ErrorKind::InvalidInput,
+            Error::Parse { .. } | Error::Protocol { .. } => ErrorKind::InvalidData,
+            Error::UnknownTid(_) => ErrorKind::Other,
+        };
+
+        io::Error::new(kind, err)
+    }
+}
+
+/// An error packet that arrived from the peer.
+impl From<Packet<packet::Error>> for Error {
+    fn from(packet: Packet<packet::Error>) -> Self {
+        Error::Remote {
+            code: packet.body.code,
+            message: packet.body.message,
+        }
+    }
+}
+
+/// The error packet that tells the peer about an error.
+///
+/// Errors that came from the peer, or that are to be sent to it, keep their
+/// code and message.
+impl From<&Error> for Packet<packet::Error> {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Remote { code, message } | Error::Protocol { code, message } => {
+                Packet::error(*code, message)
+            }
+            Error::Timeout => Packet::error(Code::NotDefined, "Transfer timed out"),
+            Error::Io(io) => Packet::error(err.code(), format!("{}", io)),
+            err => {
+                let code = err.code();
+                Packet::error(code, code.as_str())
+            }
+        }
+    }
+}
+
+/// An error packet from the peer that carries nothing but its code.
+impl From<Code> for Error {
+    fn from(code: Code) -> Self {
+        Error::Remote {
+            code,
+            message: code.as_str().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet::error(Code::FileNotFound, "no such file");
+        let err = Error::from(packet.clone());
+        assert_eq!(err.code(), Code::FileNotFound);
+        assert_eq!(Packet::from(&err), packet);
+
+        let err = Error::protocol(Code::DiskFull);
+        assert_eq!(
+            Packet::from(&err),
+            Packet::error(Code::DiskFull, Code::DiskFull.as_str())
+        );
+    }
+
+    #[test]
+    fn test_io_conversions() {
+        let err = Error::from(io::Error::from(ErrorKind::PermissionDenied));
+        assert_eq!(err.code(), Code::AccessViolation);
+        assert_eq!(io::Error::from(err).kind(), ErrorKind::PermissionDenied);
+
+        let err = io::Error::from(Error::from(Code::FileNotFound));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(io::Error::from(Error::Timeout).kind(), ErrorKind::TimedOut);
+
+        // The error itself is kept, so no code is lost along the way.
+        let err = io::Error::from(Error::from(Code::Other(0x4242)));
+        let err = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert_eq!(err.code(), Code::Other(0x4242));
+    }
+
+    #[test]
+    fn test_offset_by() {
+        let err = Error::parse(3, "truncated packet").offset_by(2);
+        assert!(matches!(err, Error::Parse { offset: 5, .. }));
+    }
+}
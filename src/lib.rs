@@ -1,5 +1,6 @@
 //! The `tftp` crate provides implementations for the following components of
-//! the Trivial File Transfer Protocol (RFC 1350):
+//! the Trivial File Transfer Protocol (RFC 1350) and its option extension
+//! (RFC 2347):
 //!
 //! * The protocol (types that represent TFTP packets as well as types that
 //!   can participate in the TFTP flow for reading or writing files with
@@ -36,14 +37,60 @@
 //! ```
 //!
 //! Alternatively, you may connect to your server from another host.
+//!
+//! ## Errors
+//!
+//! Every operation fails with an `Error`, which tells apart an error packet
+//! from the peer (such as "file not found") from a peer that stopped
+//! answering, a malformed packet or a local I/O failure.
+//!
+//! ## Storage
+//!
+//! A server reads and writes files through a `storage::Storage`, which is a
+//! directory by default. Files can also be served from memory, made up as
+//! they are asked for, laid over a directory that is kept read-only, or
+//! served from a storage of your own; see `Server::with_storage`.
+//!
+//! Files that already exist are kept unless the server is told to replace
+//! them, to keep them alongside timestamped uploads or to rotate through a
+//! number of versions of them; see `Server::overwrite`.
+//!
+//! ## Access
+//!
+//! A server grants every request unless told otherwise by an
+//! `access::AccessPolicy`, which can refuse requests by client address,
+//! filename, mode and direction; see `Server::access_policy`.
+//!
+//! ## Async
+//!
+//! With the `async` feature enabled, the client and server can also run on a
+//! tokio runtime, which lets a single thread serve many transfers at once.
+//! See the `asynchronous` module.
+//!
+//! With the `codec` feature enabled, `codec::TftpCodec` frames TFTP packets
+//! for `tokio_util::udp::UdpFramed`.
 
 #![deny(missing_docs)]
 
+pub mod access;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 mod bytes;
 pub mod client;
+#[cfg(feature = "codec")]
+pub mod codec;
 mod connection;
+mod error;
+mod machine;
+mod negotiate;
+pub mod netascii;
 pub mod packet;
 mod server;
+pub mod storage;
 
 pub use client::{Client, ConnectTo};
+pub use error::{Error, Result};
 pub use server::{Handler, Server};
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncClient, AsyncHandler, AsyncServer};
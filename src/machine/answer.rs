@@ -0,0 +1,103 @@
+//! The handling of a server's answer to a request sent by a client.
+
+use super::{unexpected, Failure};
+use crate::negotiate::{self, TransferOptions};
+use crate::packet::{AnyPacket, Oack, Options, Packet};
+
+/// Handles the answer to a read request that asked for the `requested`
+/// options.
+///
+/// Returns the negotiated options if the server answered with an `Oack`,
+/// which is yet to be acknowledged, or `None` if the answer is the first
+/// `Data` packet of the transfer.
+pub fn answer_read(
+    bytes: &[u8],
+    requested: &Options,
+    base: TransferOptions,
+) -> Result<Option<TransferOptions>, Failure> {
+    match AnyPacket::from_bytes_with(bytes, base.strictness) {
+        Ok(AnyPacket::Oack(oack)) => accept_oack(&oack, requested, base).map(Some),
+        packet @ Ok(AnyPacket::Error(_)) => Err(unexpected(packet)),
+        _ => Ok(None),
+    }
+}
+
+/// Handles the answer to a write request that asked for the `requested`
+/// options, and returns the options of the transfer.
+///
+/// The server answers with an `Oack` in place of the first `Ack` if it
+/// accepted any of the requested options.
+pub fn answer_write(
+    bytes: &[u8],
+    requested: &Options,
+    base: TransferOptions,
+) -> Result<TransferOptions, Failure> {
+    match AnyPacket::from_bytes_with(bytes, base.strictness) {
+        Ok(AnyPacket::Oack(oack)) => accept_oack(&oack, requested, base),
+        Ok(AnyPacket::Ack(_)) => Ok(base),
+        packet => Err(unexpected(packet)),
+    }
+}
+
+fn accept_oack(
+    oack: &Packet<Oack>,
+    requested: &Options,
+    base: TransferOptions,
+) -> Result<TransferOptions, Failure> {
+    negotiate::client_accept(requested, &oack.body.options, base).map_err(Failure::reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{FromBytes, IntoBytes};
+    use crate::negotiate::BLKSIZE;
+    use crate::packet::{Block, Code, Error, Mode};
+
+    #[test]
+    fn test_answer_read() {
+        let mut requested = Options::new();
+        requested.insert(BLKSIZE, "1024");
+
+        let mut accepted = Options::new();
+        accepted.insert(BLKSIZE, "1024");
+        let oack = Packet::oack(accepted).into_bytes();
+        let options = answer_read(&oack, &requested, Default::default()).unwrap();
+        assert_eq!(options.unwrap().block_size, 1024);
+
+        let data = Packet::data(Block::new(1), b"a").into_bytes();
+        assert_eq!(
+            answer_read(&data, &requested, Default::default()).unwrap(),
+            None
+        );
+
+        let error = Packet::error(Code::FileNotFound, "missing").into_bytes();
+        let failure = answer_read(&error, &requested, Default::default()).unwrap_err();
+        assert!(failure.transmit.is_none());
+        assert!(matches!(
+            failure.error,
+            crate::Error::Remote {
+                code: Code::FileNotFound,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_answer_write() {
+        let ack = Packet::ack(Block::new(0)).into_bytes();
+        let options = answer_write(&ack, &Options::new(), Default::default()).unwrap();
+        assert_eq!(options, TransferOptions::default());
+
+        // The server cannot acknowledge an option that was never requested.
+        let mut accepted = Options::new();
+        accepted.insert(BLKSIZE, "1024");
+        let oack = Packet::oack(accepted).into_bytes();
+        let failure = answer_write(&oack, &Options::new(), Default::default()).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::OptionNegotiation);
+
+        let rrq = Packet::rrq("file", Mode::Octet).into_bytes();
+        assert!(answer_write(&rrq, &Options::new(), Default::default()).is_err());
+    }
+}
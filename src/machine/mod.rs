@@ -0,0 +1,227 @@
+//! The TFTP protocol as state machines that perform no I/O of their own.
+//!
+//! A state machine is fed the packets that arrive from its peer along with
+//! the current time, and answers with the packets to send in return and the
+//! time by which it wants to hear that nothing arrived. Its driver takes care
+//! of sockets, clocks and files, so that every front end shares the same
+//! implementation of the protocol.
+
+use std::collections::vec_deque;
+use std::convert::TryFrom;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::time::Instant;
+
+use crate::bytes::IntoBytes;
+use crate::packet::{self, sealed, AnyPacket, Block, Code, Packet, Strictness};
+use crate::Error;
+
+pub use answer::{answer_read, answer_write};
+pub use receiver::Receiver;
+pub use request::{accept_read, accept_write, Request};
+pub use sender::Sender;
+
+mod answer;
+mod receiver;
+mod request;
+mod sender;
+
+/// What the driver of a state machine has to do after feeding it an input.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Output<'a> {
+    /// Where the payload that arrived in order lies within the packet that
+    /// was fed in. It is to be written out before any of the packets in
+    /// `transmit` are sent.
+    pub data: Option<Range<usize>>,
+
+    /// Packets to send to the peer, in order.
+    pub transmit: Transmit<'a>,
+
+    /// When the state machine wants to be told that nothing arrived. `None`
+    /// once the transfer is over.
+    pub deadline: Option<Instant>,
+}
+
+impl Output<'_> {
+    fn wait_until(deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..Self::default()
+        }
+    }
+}
+
+/// The packets a state machine wants sent, borrowed from the state machine.
+///
+/// Every packet that may have to be retransmitted is kept by the state
+/// machine anyway, so they are handed out in place rather than copied.
+#[derive(Clone, Debug, Default)]
+pub struct Transmit<'a> {
+    packet: Option<&'a [u8]>,
+    window: vec_deque::Iter<'a, (Block, Vec<u8>)>,
+}
+
+impl<'a> Transmit<'a> {
+    /// A single packet.
+    fn one(packet: &'a [u8]) -> Self {
+        Self {
+            packet: Some(packet),
+            ..Self::default()
+        }
+    }
+
+    /// Blocks of a sender's window.
+    fn window(window: vec_deque::Iter<'a, (Block, Vec<u8>)>) -> Self {
+        Self {
+            packet: None,
+            window,
+        }
+    }
+}
+
+impl<'a> Iterator for Transmit<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        match self.packet.take() {
+            Some(packet) => Some(packet),
+            None => self.window.next().map(|(_, packet)| &packet[..]),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = usize::from(self.packet.is_some()) + self.window.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Transmit<'_> {}
+
+impl PartialEq for Transmit<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        Iterator::eq(self.clone(), other.clone())
+    }
+}
+
+impl Eq for Transmit<'_> {}
+
+/// The reason a transfer cannot go on.
+#[derive(Debug)]
+pub struct Failure {
+    /// The error packet that tells the peer, unless the peer is the one that
+    /// gave up.
+    pub transmit: Option<Vec<u8>>,
+
+    /// The error to report locally.
+    pub error: Error,
+}
+
+impl Failure {
+    /// A failure that the peer is told about.
+    pub fn reply(error: Error) -> Self {
+        let packet = Packet::<packet::Error>::from(&error);
+
+        Self {
+            transmit: Some(packet.into_bytes()),
+            error,
+        }
+    }
+
+    /// A local error, such as a file that could not be read.
+    pub fn local(error: io::Error) -> Self {
+        Self::reply(Error::Io(error))
+    }
+
+    /// The peer stayed quiet for too long.
+    fn timed_out() -> Self {
+        Self::reply(Error::Timeout)
+    }
+}
+
+impl From<Failure> for Error {
+    fn from(failure: Failure) -> Error {
+        failure.error
+    }
+}
+
+/// Parses a packet of the expected type.
+///
+/// Anything else ends the transfer. The peer is told that it broke the
+/// protocol, unless it sent an error packet of its own.
+pub fn expect<P: sealed::Packet>(
+    bytes: &[u8],
+    strictness: Strictness,
+) -> Result<Packet<P>, Failure> {
+    match AnyPacket::from_bytes_with(bytes, strictness) {
+        Ok(packet) => Packet::try_from(packet).map_err(|packet| unexpected(Ok(packet))),
+        Err(err) => Err(unexpected(Err(err))),
+    }
+}
+
+/// The failure that a packet of the wrong type, or one that could not be
+/// parsed at all, leads to.
+fn unexpected(packet: crate::Result<AnyPacket>) -> Failure {
+    match packet {
+        Ok(AnyPacket::Error(error)) => Failure {
+            transmit: None,
+            error: error.into(),
+        },
+        Ok(_) => Failure::reply(Error::protocol(Code::IllegalOperation)),
+        Err(err) => Failure::reply(err),
+    }
+}
+
+/// The error packet that turns away a packet from the unknown Transfer ID
+/// `src` (RFC 1350 section 4).
+pub fn unknown_tid(src: SocketAddr) -> Vec<u8> {
+    Packet::<packet::Error>::from(&Error::UnknownTid(src)).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::FromBytes;
+    use crate::packet::{Ack, Block, Data};
+
+    #[test]
+    fn test_expect() {
+        let ack = Packet::ack(Block::new(1)).into_bytes();
+        assert!(expect::<Ack>(&ack, Strictness::Strict).is_ok());
+
+        let failure = expect::<Data>(&ack, Strictness::Strict).unwrap_err();
+        let reply = Packet::<packet::Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(reply.body.code, Code::IllegalOperation);
+        assert!(matches!(failure.error, Error::Protocol { .. }));
+
+        let failure = expect::<Data>(&[0, 3, 0], Strictness::Strict).unwrap_err();
+        let reply = Packet::<packet::Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(reply.body.code, Code::IllegalOperation);
+        assert!(matches!(failure.error, Error::Parse { offset: 3, .. }));
+
+        let error = Packet::error(Code::DiskFull, "full").into_bytes();
+        let failure = expect::<Data>(&error, Strictness::Strict).unwrap_err();
+        assert!(failure.transmit.is_none());
+        match failure.error {
+            Error::Remote { code, message } => {
+                assert_eq!(code, Code::DiskFull);
+                assert_eq!(message, "full");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        // Codes this crate does not know about still end the transfer
+        // without a reply.
+        let error = Packet::error(Code::Other(0x4242), "vendor").into_bytes();
+        let failure = expect::<Data>(&error, Strictness::Strict).unwrap_err();
+        assert!(failure.transmit.is_none());
+        assert_eq!(failure.error.code(), Code::Other(0x4242));
+
+        // An error without its terminator only passes leniently.
+        let failure = expect::<Data>(b"\x00\x05\x00\x01gone", Strictness::Strict).unwrap_err();
+        assert!(failure.transmit.is_some());
+        let failure = expect::<Data>(b"\x00\x05\x00\x01gone", Strictness::Lenient).unwrap_err();
+        assert!(failure.transmit.is_none());
+        assert!(matches!(failure.error, Error::Remote { ref message, .. } if message == "gone"));
+    }
+}
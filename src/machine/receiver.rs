@@ -0,0 +1,416 @@
+//! The receiving side of a transfer.
+
+use std::time::Instant;
+
+use super::{unexpected, Failure, Output, Transmit};
+use crate::negotiate::TransferOptions;
+use crate::packet::{AnyPacket, Block, Code, DataRef, Packet, DATA_HEADER_SIZE};
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Receiving,
+
+    /// The final block was acknowledged, but the acknowledgement may still
+    /// get lost. This many more packets are answered.
+    Dallying(u32),
+
+    Finished,
+}
+
+/// Receives a file, acknowledging the last block of every window.
+///
+/// A block that arrives ahead of time means that an earlier one was lost.
+/// The last block received in order is acknowledged once, which tells the
+/// sender to resume right after it. The same acknowledgement is retransmitted
+/// whenever the sender goes quiet, and whenever a block that was already
+/// received arrives again.
+#[derive(Debug)]
+pub struct Receiver {
+    options: TransferOptions,
+    state: State,
+    retries: u32,
+
+    /* retransmitted in place of `last_ack` until a block is acknowledged */
+    opening: Option<Vec<u8>>,
+
+    /* the packet to retransmit while the sender is quiet */
+    last_ack: [u8; 4],
+
+    /* the acknowledgement of a block that arrived twice */
+    reack: [u8; 4],
+
+    // Block numbers wrap around in large files, so blocks are counted
+    // separately to tell which one comes next.
+    received: u64,
+    received_bytes: u64,
+    last_block: Block,
+    received_in_window: u16,
+    gap_acked: bool,
+}
+
+impl Receiver {
+    /// Creates a receiver that starts the transfer by sending `opening`, if
+    /// given, which is retransmitted until the first block arrives.
+    ///
+    /// This is the `Ack` or `Oack` that accepts a write request, or the `Ack`
+    /// that the first `Data` packet of a read request has to wait on after
+    /// an `Oack`.
+    pub fn new(options: TransferOptions, opening: Option<Vec<u8>>) -> Self {
+        let last_block = options.rollover.block(0);
+
+        Self {
+            options,
+            state: State::Receiving,
+            retries: options.retries,
+            opening,
+            last_ack: ack(last_block),
+            reack: ack(last_block),
+            received: 0,
+            received_bytes: 0,
+            last_block,
+            received_in_window: 0,
+            gap_acked: false,
+        }
+    }
+
+    /// Starts the transfer.
+    ///
+    /// A receiver without an opening packet waits for the first block, which
+    /// the sender sends without being asked.
+    pub fn start(&mut self, now: Instant) -> Output<'_> {
+        let mut output = Output::wait_until(now + self.options.timeout);
+        if let Some(ref opening) = self.opening {
+            output.transmit = Transmit::one(opening);
+        }
+        output
+    }
+
+    /// Handles a packet from the sender.
+    pub fn recv(&mut self, bytes: &[u8], now: Instant) -> Result<Output<'_>, Failure> {
+        let mut output = Output::wait_until(now + self.options.timeout);
+
+        match self.state {
+            State::Receiving => (),
+            State::Dallying(remaining) => {
+                if let Ok(data) = DataRef::parse(bytes) {
+                    if !self.last_block.precedes(data.block) {
+                        output.transmit = Transmit::one(&self.last_ack);
+                    }
+                }
+
+                if remaining == 0 {
+                    self.state = State::Finished;
+                    output.deadline = None;
+                } else {
+                    self.state = State::Dallying(remaining - 1);
+                }
+                return Ok(output);
+            }
+            State::Finished => return Ok(Output::default()),
+        }
+
+        self.retries = self.options.retries;
+
+        // Blocks are looked at in place, and only the rare other packet is
+        // parsed in full.
+        let data = match DataRef::parse(bytes) {
+            Ok(data) => data,
+            Err(_) => match AnyPacket::from_bytes_with(bytes, self.options.strictness) {
+                // The sender keeps retransmitting its `Oack` until our first
+                // acknowledgement gets through.
+                Ok(AnyPacket::Oack(_)) => {
+                    if self.received == 0 {
+                        output.transmit = Transmit::one(self.resend());
+                    }
+                    return Ok(output);
+                }
+                packet => return Err(unexpected(packet)),
+            },
+        };
+
+        if data.data.len() > self.options.block_size {
+            return Err(Failure::reply(Error::protocol(Code::IllegalOperation)));
+        }
+
+        let expected = self.options.rollover.block(self.received + 1);
+
+        // A block we already have means the sender missed our
+        // acknowledgement. It is acknowledged again, but not passed on.
+        if data.block.precedes(expected) {
+            self.reack = ack(self.last_block);
+            self.received_in_window = 0;
+            output.transmit = Transmit::one(&self.reack);
+            return Ok(output);
+        }
+
+        if data.block != expected {
+            if !self.gap_acked {
+                self.acknowledge();
+                self.gap_acked = true;
+                output.transmit = Transmit::one(&self.last_ack);
+            }
+            return Ok(output);
+        }
+
+        self.received_bytes += data.data.len() as u64;
+        if self
+            .options
+            .max_size
+            .is_some_and(|max| self.received_bytes > max)
+        {
+            return Err(Failure::reply(Error::protocol(Code::DiskFull)));
+        }
+
+        self.received += 1;
+        self.last_block = data.block;
+        self.received_in_window += 1;
+        self.gap_acked = false;
+
+        let is_last = data.data.len() < self.options.block_size;
+
+        // Linger in case the final acknowledgement is lost and the sender
+        // retransmits the final block.
+        if is_last {
+            self.state = State::Dallying(self.options.retries);
+        }
+
+        output.data = Some(DATA_HEADER_SIZE..bytes.len());
+        if is_last || self.received_in_window == self.options.window_size {
+            self.acknowledge();
+            output.transmit = Transmit::one(&self.last_ack);
+        }
+        Ok(output)
+    }
+
+    /// Handles the sender staying quiet past the deadline.
+    pub fn timeout(&mut self, now: Instant) -> Result<Output<'_>, Failure> {
+        match self.state {
+            State::Receiving => (),
+            State::Dallying(_) | State::Finished => {
+                self.state = State::Finished;
+                return Ok(Output::default());
+            }
+        }
+
+        if self.retries == 0 {
+            return Err(Failure::timed_out());
+        }
+        self.retries -= 1;
+
+        let mut output = Output::wait_until(now + self.options.timeout);
+        output.transmit = Transmit::one(self.resend());
+        Ok(output)
+    }
+
+    /// Acknowledges every block received so far, which takes the place of
+    /// the opening packet from now on.
+    fn acknowledge(&mut self) {
+        self.last_ack = ack(self.last_block);
+        self.opening = None;
+        self.received_in_window = 0;
+    }
+
+    /// The packet to retransmit while the sender is quiet.
+    fn resend(&self) -> &[u8] {
+        match self.opening {
+            Some(ref opening) => opening,
+            None => &self.last_ack,
+        }
+    }
+}
+
+/// Encodes an acknowledgement without going through an owned packet.
+fn ack(block: Block) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    Packet::ack(block)
+        .encode(&mut bytes)
+        .expect("an ack takes 4 bytes");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bytes::{FromBytes, IntoBytes};
+    use crate::packet::{Ack, Error, Options, Rollover};
+
+    fn options(window_size: u16) -> TransferOptions {
+        TransferOptions {
+            block_size: 8,
+            retries: 2,
+            window_size,
+            ..TransferOptions::default()
+        }
+    }
+
+    fn data(block: u16, data: &[u8]) -> Vec<u8> {
+        Packet::data(Block::new(block), data).into_bytes()
+    }
+
+    fn acks(output: &Output) -> Vec<u16> {
+        output
+            .transmit
+            .clone()
+            .map(|bytes| {
+                assert!(Packet::<Ack>::from_bytes(bytes).is_ok());
+                u16::from_be_bytes([bytes[2], bytes[3]])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_acks_once_per_window() {
+        let now = Instant::now();
+        let mut receiver = Receiver::new(options(2), None);
+        assert_eq!(receiver.start(now).transmit.len(), 0);
+
+        let output = receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        assert_eq!(output.data, Some(4..12));
+        assert_eq!(output.transmit.len(), 0);
+
+        let output = receiver.recv(&data(2, b"bbbbbbbb"), now).unwrap();
+        assert_eq!(acks(&output), vec![2]);
+
+        let output = receiver.recv(&data(3, b"c"), now).unwrap();
+        assert_eq!(acks(&output), vec![3]);
+    }
+
+    #[test]
+    fn test_acks_gap_once() {
+        let now = Instant::now();
+        let mut receiver = Receiver::new(options(4), None);
+
+        receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+
+        // Block 2 is lost; the receiver asks for everything after block 1.
+        let output = receiver.recv(&data(3, b"cccccccc"), now).unwrap();
+        assert_eq!(acks(&output), vec![1]);
+        assert_eq!(output.data, None);
+        let output = receiver.recv(&data(4, b"dddddddd"), now).unwrap();
+        assert_eq!(output.transmit.len(), 0);
+
+        let output = receiver.recv(&data(2, b"bbbbbbbb"), now).unwrap();
+        assert_eq!(output.data, Some(4..12));
+    }
+
+    #[test]
+    fn test_reacks_duplicate_block() {
+        let now = Instant::now();
+        let mut receiver = Receiver::new(options(1), None);
+
+        let output = receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        assert_eq!(acks(&output), vec![1]);
+
+        // The acknowledgement was lost, so the sender tries again.
+        let output = receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        assert_eq!(acks(&output), vec![1]);
+        assert_eq!(output.data, None);
+    }
+
+    #[test]
+    fn test_ignores_duplicate_oack() {
+        let now = Instant::now();
+        let ack = Packet::ack(Block::new(0)).into_bytes();
+        let mut receiver = Receiver::new(options(1), Some(ack));
+        assert_eq!(acks(&receiver.start(now)), vec![0]);
+
+        // The first acknowledgement is slow, so the `Oack` is retransmitted.
+        let oack = Packet::oack(Options::new()).into_bytes();
+        assert_eq!(acks(&receiver.recv(&oack, now).unwrap()), vec![0]);
+
+        receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        assert_eq!(receiver.recv(&oack, now).unwrap().transmit.len(), 0);
+    }
+
+    #[test]
+    fn test_times_out() {
+        let now = Instant::now();
+        let mut receiver = Receiver::new(options(1), None);
+        let deadline = receiver.start(now).deadline.unwrap();
+        assert_eq!(deadline, now + Duration::from_secs(1));
+
+        receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+
+        // Block 2 was lost, so the acknowledgement for block 1 is repeated.
+        for _ in 0..2 {
+            let output = receiver.timeout(deadline).unwrap();
+            assert_eq!(acks(&output), vec![1]);
+            assert_eq!(output.deadline, Some(deadline + Duration::from_secs(1)));
+        }
+
+        let failure = receiver.timeout(deadline).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::NotDefined);
+        assert!(matches!(failure.error, crate::Error::Timeout));
+    }
+
+    #[test]
+    fn test_dallies_after_final_ack() {
+        let now = Instant::now();
+        let mut receiver = Receiver::new(options(1), None);
+
+        let output = receiver.recv(&data(1, b"a"), now).unwrap();
+        assert_eq!(acks(&output), vec![1]);
+        assert!(output.deadline.is_some());
+
+        // The final acknowledgement was lost.
+        let output = receiver.recv(&data(1, b"a"), now).unwrap();
+        assert_eq!(acks(&output), vec![1]);
+        assert_eq!(output.data, None);
+
+        assert_eq!(receiver.timeout(now).unwrap(), Output::default());
+    }
+
+    #[test]
+    fn test_rejects_oversized_block() {
+        let mut receiver = Receiver::new(options(1), None);
+
+        let failure = receiver
+            .recv(&data(1, b"aaaaaaaaa"), Instant::now())
+            .unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::IllegalOperation);
+    }
+
+    #[test]
+    fn test_refuses_too_much_data() {
+        let now = Instant::now();
+        let limited = TransferOptions {
+            max_size: Some(10),
+            ..options(1)
+        };
+        let mut receiver = Receiver::new(limited, None);
+
+        receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        let failure = receiver.recv(&data(2, b"bbbbbbbb"), now).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::DiskFull);
+
+        // Exactly as much as allowed is fine.
+        let mut receiver = Receiver::new(limited, None);
+        receiver.recv(&data(1, b"aaaaaaaa"), now).unwrap();
+        let output = receiver.recv(&data(2, b"bb"), now).unwrap();
+        assert_eq!(output.data, Some(4..6));
+    }
+
+    #[test]
+    fn test_rolls_over() {
+        let now = Instant::now();
+        let rollover = TransferOptions {
+            rollover: Rollover::One,
+            ..options(1)
+        };
+        let mut receiver = Receiver::new(rollover, None);
+
+        for block in 1..=u16::MAX {
+            receiver.recv(&data(block, b"aaaaaaaa"), now).unwrap();
+        }
+
+        let output = receiver.recv(&data(1, b"a"), now).unwrap();
+        assert_eq!(output.data, Some(4..5));
+        assert_eq!(acks(&output), vec![1]);
+    }
+}
@@ -0,0 +1,203 @@
+//! The handling of a request received by a server.
+
+use super::{Failure, Receiver, Sender};
+use crate::bytes::IntoBytes;
+use crate::negotiate::{self, Limits, TransferOptions};
+use crate::packet::{AnyPacket, Block, Code, Mode, Packet, Rrq, Strictness, Wrq};
+use crate::Error;
+
+/// A request that starts a transfer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Request {
+    /// The client reads a file.
+    Read(Packet<Rrq>),
+
+    /// The client writes a file.
+    Write(Packet<Wrq>),
+}
+
+impl Request {
+    /// Parses a request, which is the only kind of packet a server accepts
+    /// outside of a transfer.
+    pub fn parse(bytes: &[u8], strictness: Strictness) -> Result<Self, Failure> {
+        match AnyPacket::from_bytes_with(bytes, strictness) {
+            Ok(AnyPacket::Rrq(rrq)) => Ok(Request::Read(rrq)),
+            Ok(AnyPacket::Wrq(wrq)) => Ok(Request::Write(wrq)),
+            Ok(_) => Err(Failure::reply(Error::protocol(Code::IllegalOperation))),
+            Err(err) => Err(Failure::reply(err)),
+        }
+    }
+}
+
+/// Accepts a read request for a file of `file_size` bytes, if known, and
+/// negotiates the options of the transfer.
+///
+/// The resulting `Sender` opens with an `Oack` if any options were accepted.
+pub fn accept_read(
+    rrq: &Packet<Rrq>,
+    file_size: Option<u64>,
+    base: TransferOptions,
+    limits: &Limits,
+) -> Result<Sender, Failure> {
+    // A file grows when it is translated to netascii, so its size is only
+    // known up front in octet mode.
+    let file_size = match rrq.body.0.mode {
+        Mode::NetAscii => None,
+        _ => file_size,
+    };
+
+    let request = negotiate::Request::Read { file_size };
+    let (oack, options) = negotiate::server_accept(&rrq.body.0.options, base, limits, request)
+        .map_err(Failure::reply)?;
+
+    // The client acknowledges an `Oack` with block 0 before the first
+    // `Data` packet is sent.
+    let opening = if oack.is_empty() {
+        None
+    } else {
+        Some(Packet::oack(oack).into_bytes())
+    };
+
+    Ok(Sender::new(options, opening))
+}
+
+/// Accepts a write request and negotiates the options of the transfer.
+///
+/// The resulting `Receiver` opens with an `Oack` if any options were
+/// accepted, or an `Ack` otherwise. It ends the transfer once the client
+/// sends more than it announced, or more than the limits allow.
+pub fn accept_write(
+    wrq: &Packet<Wrq>,
+    base: TransferOptions,
+    limits: &Limits,
+) -> Result<Receiver, Failure> {
+    let request = negotiate::Request::Write;
+    let (oack, mut options) = negotiate::server_accept(&wrq.body.0.options, base, limits, request)
+        .map_err(Failure::reply)?;
+
+    // A file grows when it is translated to netascii, so the size a client
+    // announces is only held against it in octet mode.
+    let announced = match wrq.body.0.mode {
+        Mode::NetAscii => None,
+        _ => options.transfer_size,
+    };
+    options.max_size = [options.max_size, announced, limits.max_transfer_size]
+        .iter()
+        .flatten()
+        .copied()
+        .min();
+
+    // An `Oack` takes the place of the first `Ack`.
+    let opening = if oack.is_empty() {
+        Packet::ack(Block::new(0)).into_bytes()
+    } else {
+        Packet::oack(oack).into_bytes()
+    };
+
+    Ok(Receiver::new(options, Some(opening)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::bytes::FromBytes;
+    use crate::negotiate::TSIZE;
+    use crate::packet::{Error, Oack, Options};
+
+    #[test]
+    fn test_parse() {
+        let rrq = Packet::rrq("file", Mode::Octet);
+        let request = Request::parse(&rrq.clone().into_bytes(), Strictness::Strict).unwrap();
+        assert_eq!(request, Request::Read(rrq));
+
+        let wrq = Packet::wrq("file", Mode::NetAscii);
+        let request = Request::parse(&wrq.clone().into_bytes(), Strictness::Strict).unwrap();
+        assert_eq!(request, Request::Write(wrq));
+
+        let ack = Packet::ack(Block::new(0)).into_bytes();
+        let failure = Request::parse(&ack, Strictness::Strict).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::IllegalOperation);
+
+        // A mode without its nul terminator, as some bootloaders send it.
+        let quirky = b"\x00\x01file\0octet";
+        assert!(Request::parse(quirky, Strictness::Strict).is_err());
+        let request = Request::parse(quirky, Strictness::Lenient).unwrap();
+        assert_eq!(request, Request::Read(Packet::rrq("file", Mode::Octet)));
+    }
+
+    #[test]
+    fn test_accept_read() {
+        let mut options = Options::new();
+        options.insert(TSIZE, "0");
+
+        let rrq = Packet::rrq_with_options("file", Mode::Octet, options.clone());
+        let mut sender =
+            accept_read(&rrq, Some(42), Default::default(), &Default::default()).unwrap();
+        let opening = sender.start(Instant::now()).transmit.next().unwrap();
+        let oack = Packet::<Oack>::from_bytes(opening).unwrap();
+        assert_eq!(oack.body.options.get(TSIZE), Some("42"));
+
+        // The size is not known in netascii mode, and nothing else is left
+        // to acknowledge.
+        let rrq = Packet::rrq_with_options("file", Mode::NetAscii, options);
+        let mut sender =
+            accept_read(&rrq, Some(42), Default::default(), &Default::default()).unwrap();
+        assert_eq!(sender.start(Instant::now()).transmit.len(), 0);
+    }
+
+    #[test]
+    fn test_accept_write() {
+        let wrq = Packet::wrq("file", Mode::Octet);
+        let mut receiver = accept_write(&wrq, Default::default(), &Default::default()).unwrap();
+        let opening = receiver.start(Instant::now()).transmit.next().unwrap();
+        assert_eq!(opening, Packet::ack(Block::new(0)).into_bytes());
+
+        let mut options = Options::new();
+        options.insert(TSIZE, "1025");
+        let wrq = Packet::wrq_with_options("file", Mode::Octet, options);
+        let limits = Limits {
+            max_transfer_size: Some(1024),
+            ..Limits::default()
+        };
+        let failure = accept_write(&wrq, Default::default(), &limits).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::DiskFull);
+    }
+
+    #[test]
+    fn test_accept_write_holds_to_size() {
+        let now = Instant::now();
+        let data = Packet::data(Block::new(1), vec![b'a'; 512]).into_bytes();
+
+        // The client announced less than it sends.
+        let mut options = Options::new();
+        options.insert(TSIZE, "10");
+        let wrq = Packet::wrq_with_options("file", Mode::Octet, options);
+        let mut receiver = accept_write(&wrq, Default::default(), &Default::default()).unwrap();
+        receiver.start(now);
+        let failure = receiver.recv(&data, now).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::DiskFull);
+
+        // Nothing was announced, but the limit still holds.
+        let wrq = Packet::wrq("file", Mode::Octet);
+        let limits = Limits {
+            max_transfer_size: Some(511),
+            ..Limits::default()
+        };
+        let mut receiver = accept_write(&wrq, Default::default(), &limits).unwrap();
+        receiver.start(now);
+        assert!(receiver.recv(&data, now).is_err());
+
+        let limits = Limits {
+            max_transfer_size: Some(512),
+            ..Limits::default()
+        };
+        let mut receiver = accept_write(&wrq, Default::default(), &limits).unwrap();
+        receiver.start(now);
+        assert!(receiver.recv(&data, now).is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+//! The sending side of a transfer.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use super::{expect, Failure, Output, Transmit};
+use crate::negotiate::TransferOptions;
+use crate::packet::{Ack, Block, DataRef, Packet};
+
+/// Sends a file, keeping up to a window's worth of blocks in flight.
+///
+/// An acknowledgement for a block in the middle of the window means the
+/// blocks after it were lost, so they are sent again. The whole window is
+/// retransmitted whenever the receiver goes quiet.
+///
+/// Only the first acknowledgement of a block moves the window forward.
+/// Duplicates are ignored rather than answered with more data, which avoids
+/// the Sorcerer's Apprentice bug described in RFC 1123.
+#[derive(Debug)]
+pub struct Sender {
+    options: TransferOptions,
+    retries: u32,
+
+    /* when the receiver has been quiet for too long; only progress moves it */
+    deadline: Instant,
+
+    /* retransmitted until the receiver acknowledges block 0 */
+    opening: Option<Vec<u8>>,
+
+    /* blocks in flight, encoded once and kept for retransmission */
+    window: VecDeque<(Block, Vec<u8>)>,
+
+    /* buffers of acknowledged blocks, reused for the blocks that follow */
+    spare: Vec<Vec<u8>>,
+    next_index: u64,
+    read_everything: bool,
+}
+
+impl Sender {
+    /// Creates a sender that starts the transfer by sending `opening`, if
+    /// given, and waiting for the receiver to acknowledge it with block 0.
+    ///
+    /// This is the `Oack` that accepts a read request.
+    pub fn new(options: TransferOptions, opening: Option<Vec<u8>>) -> Self {
+        Self {
+            options,
+            retries: options.retries,
+            deadline: Instant::now(),
+            opening,
+            window: VecDeque::with_capacity(usize::from(options.window_size)),
+            spare: Vec::new(),
+            next_index: 1,
+            read_everything: false,
+        }
+    }
+
+    /// The number of bytes every block but the last has to hold.
+    pub fn block_size(&self) -> usize {
+        self.options.block_size
+    }
+
+    /// Starts the transfer.
+    pub fn start(&mut self, now: Instant) -> Output<'_> {
+        self.deadline = now + self.options.timeout;
+        let mut output = Output::wait_until(self.deadline);
+        if let Some(ref opening) = self.opening {
+            output.transmit = Transmit::one(opening);
+        }
+        output
+    }
+
+    /// Returns `true` if there is room in the window for another block.
+    ///
+    /// A block that is shorter than the block size is the last one.
+    pub fn wants_block(&self) -> bool {
+        self.opening.is_none()
+            && !self.read_everything
+            && self.window.len() < usize::from(self.options.window_size)
+    }
+
+    /// Sends the next block of the file.
+    pub fn push_block(&mut self, block: &[u8], now: Instant) -> Output<'_> {
+        let data = DataRef::new(self.options.rollover.block(self.next_index), block);
+        self.next_index += 1;
+        self.read_everything = block.len() < self.options.block_size;
+
+        let mut packet = self.spare.pop().unwrap_or_default();
+        packet.resize(data.encoded_len(), 0);
+        data.encode(&mut packet)
+            .expect("the packet is sized to fit the block");
+        self.window.push_back((data.block, packet));
+
+        self.deadline = now + self.options.timeout;
+        let (_, packet) = self.window.back().expect("a block was just pushed");
+        let mut output = Output::wait_until(self.deadline);
+        output.transmit = Transmit::one(packet);
+        output
+    }
+
+    /// Returns `true` once every block has been acknowledged.
+    pub fn is_finished(&self) -> bool {
+        self.opening.is_none() && self.read_everything && self.window.is_empty()
+    }
+
+    /// Handles a packet from the receiver.
+    pub fn recv(&mut self, bytes: &[u8], now: Instant) -> Result<Output<'_>, Failure> {
+        if self.is_finished() {
+            return Ok(Output::default());
+        }
+
+        let ack: Packet<Ack> = expect(bytes, self.options.strictness)?;
+
+        // Acknowledgements that do not move the transfer forward are stale.
+        // They leave the deadline alone, or a receiver that keeps repeating
+        // its last acknowledgement would hold off the retransmission of a
+        // lost block for good.
+        if self.opening.is_some() {
+            if ack.body.block == Block::new(0) {
+                self.opening = None;
+                self.progress(now);
+            }
+            return Ok(Output::wait_until(self.deadline));
+        }
+
+        let acked = match self
+            .window
+            .iter()
+            .position(|(block, _)| *block == ack.body.block)
+        {
+            Some(idx) => idx + 1,
+            None => return Ok(Output::wait_until(self.deadline)),
+        };
+        for (_, packet) in self.window.drain(..acked) {
+            self.spare.push(packet);
+        }
+        self.progress(now);
+
+        let mut output = Output::wait_until(self.deadline);
+        output.transmit = Transmit::window(self.window.iter());
+        if self.is_finished() {
+            output.deadline = None;
+        }
+
+        Ok(output)
+    }
+
+    /// Handles the receiver staying quiet past the deadline.
+    pub fn timeout(&mut self, now: Instant) -> Result<Output<'_>, Failure> {
+        if self.is_finished() {
+            return Ok(Output::default());
+        }
+
+        if self.retries == 0 {
+            return Err(Failure::timed_out());
+        }
+        self.retries -= 1;
+
+        self.deadline = now + self.options.timeout;
+        let mut output = Output::wait_until(self.deadline);
+        output.transmit = match self.opening {
+            Some(ref opening) => Transmit::one(opening),
+            None => Transmit::window(self.window.iter()),
+        };
+
+        Ok(output)
+    }
+
+    /// Gives the receiver a fresh set of retries after it acknowledged
+    /// something new.
+    fn progress(&mut self, now: Instant) {
+        self.retries = self.options.retries;
+        self.deadline = now + self.options.timeout;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bytes::{FromBytes, IntoBytes};
+    use crate::machine::Receiver;
+    use crate::packet::{Code, Data, Error, Options, Rollover};
+
+    fn options(window_size: u16) -> TransferOptions {
+        TransferOptions {
+            block_size: 8,
+            retries: 2,
+            window_size,
+            ..TransferOptions::default()
+        }
+    }
+
+    fn ack(block: u16) -> Vec<u8> {
+        Packet::ack(Block::new(block)).into_bytes()
+    }
+
+    fn blocks(output: &Output) -> Vec<u16> {
+        output
+            .transmit
+            .clone()
+            .map(|bytes| {
+                assert!(Packet::<Data>::from_bytes(bytes).is_ok());
+                u16::from_be_bytes([bytes[2], bytes[3]])
+            })
+            .collect()
+    }
+
+    /// The first packet to send.
+    fn first<'a>(output: &Output<'a>) -> &'a [u8] {
+        output.transmit.clone().next().unwrap()
+    }
+
+    /// Hands the sender blocks until its window is full.
+    fn fill(sender: &mut Sender, input: &mut &[u8], now: Instant) -> Vec<u16> {
+        let mut sent = Vec::new();
+        while sender.wants_block() {
+            let len = input.len().min(sender.block_size());
+            let (block, rest) = input.split_at(len);
+            sent.append(&mut blocks(&sender.push_block(block, now)));
+            *input = rest;
+        }
+        sent
+    }
+
+    #[test]
+    fn test_resends_rest_of_window() {
+        let now = Instant::now();
+        let mut input = &b"aaaaaaaabbbbbbbbccccccccddddddddeeeeeeeeff"[..];
+        let mut sender = Sender::new(options(4), None);
+        sender.start(now);
+
+        assert_eq!(fill(&mut sender, &mut input, now), vec![1, 2, 3, 4]);
+
+        // Only the first two blocks made it to the receiver.
+        let output = sender.recv(&ack(2), now).unwrap();
+        assert_eq!(blocks(&output), vec![3, 4]);
+        assert_eq!(fill(&mut sender, &mut input, now), vec![5, 6]);
+
+        let output = sender.recv(&ack(6), now).unwrap();
+        assert!(output.deadline.is_none());
+        assert!(sender.is_finished());
+    }
+
+    #[test]
+    fn test_reuses_block_buffers() {
+        let now = Instant::now();
+        let mut sender = Sender::new(options(1), None);
+        sender.start(now);
+
+        let buffer = first(&sender.push_block(b"aaaaaaaa", now)).as_ptr();
+        sender.recv(&ack(1), now).unwrap();
+
+        // The buffer of an acknowledged block holds the next one.
+        let output = sender.push_block(b"b", now);
+        assert_eq!(blocks(&output), vec![2]);
+        assert_eq!(first(&output).as_ptr(), buffer);
+    }
+
+    #[test]
+    fn test_ignores_duplicate_ack() {
+        let now = Instant::now();
+        let mut input = &b"aaaaaaaabbbbbbbbc"[..];
+        let mut sender = Sender::new(options(1), None);
+        sender.start(now);
+
+        assert_eq!(fill(&mut sender, &mut input, now), vec![1]);
+
+        // The first acknowledgement is late, so block 1 is sent twice and
+        // acknowledged twice.
+        assert_eq!(blocks(&sender.timeout(now).unwrap()), vec![1]);
+        assert_eq!(sender.recv(&ack(1), now).unwrap().transmit.len(), 0);
+        assert_eq!(fill(&mut sender, &mut input, now), vec![2]);
+
+        // Block 2 must still only be sent once.
+        assert_eq!(sender.recv(&ack(1), now).unwrap().transmit.len(), 0);
+        assert!(!sender.wants_block());
+    }
+
+    #[test]
+    fn test_resends_lost_block_despite_stale_acks() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut input = &b"aaaaaaaabbbbbbbbc"[..];
+        let mut sender = Sender::new(options(1), None);
+        let mut receiver = Receiver::new(options(1), None);
+        sender.start(now);
+        receiver.start(now);
+
+        let output = sender.push_block(&input[..8], now);
+        input = &input[8..];
+        let output = receiver.recv(first(&output), now).unwrap();
+        sender.recv(first(&output), now).unwrap();
+
+        // Block 2 is lost, so the receiver repeats its acknowledgement of
+        // block 1 when it times out, which is just before the sender does.
+        assert_eq!(fill(&mut sender, &mut input, now + second), vec![2]);
+        let deadline = now + 2 * second;
+        for _ in 0..2 {
+            let reack = receiver.timeout(deadline).unwrap();
+            let output = sender.recv(first(&reack), deadline).unwrap();
+            assert_eq!(output.transmit.len(), 0);
+            assert_eq!(output.deadline, Some(deadline));
+        }
+
+        let output = sender.timeout(deadline).unwrap();
+        assert_eq!(blocks(&output), vec![2]);
+        let output = receiver.recv(first(&output), deadline).unwrap();
+        assert_eq!(output.data, Some(4..12));
+    }
+
+    #[test]
+    fn test_awaits_opening_ack() {
+        let now = Instant::now();
+        let oack = Packet::oack(Options::new()).into_bytes();
+        let mut sender = Sender::new(options(1), Some(oack.clone()));
+
+        assert_eq!(sender.start(now).transmit, Transmit::one(&oack));
+        assert!(!sender.wants_block());
+        assert_eq!(sender.timeout(now).unwrap().transmit, Transmit::one(&oack));
+
+        sender.recv(&ack(0), now).unwrap();
+        assert!(sender.wants_block());
+    }
+
+    #[test]
+    fn test_gives_up_after_retries() {
+        let now = Instant::now();
+        let mut input = &b"a"[..];
+        let mut sender = Sender::new(options(1), None);
+        sender.start(now);
+        fill(&mut sender, &mut input, now);
+
+        for _ in 0..2 {
+            assert_eq!(blocks(&sender.timeout(now).unwrap()), vec![1]);
+        }
+
+        let failure = sender.timeout(now).unwrap_err();
+        let error = Packet::<Error>::from_bytes(failure.transmit.unwrap()).unwrap();
+        assert_eq!(error.body.code, Code::NotDefined);
+        assert!(matches!(failure.error, crate::Error::Timeout));
+    }
+
+    #[test]
+    fn test_rolls_over_to_one() {
+        let now = Instant::now();
+        let rollover = TransferOptions {
+            rollover: Rollover::One,
+            ..options(1)
+        };
+        let mut sender = Sender::new(rollover, None);
+        sender.start(now);
+
+        let mut sent = Vec::new();
+        for _ in 0..u16::MAX {
+            sent = blocks(&sender.push_block(b"aaaaaaaa", now));
+            sender.recv(&ack(sent[0]), now).unwrap();
+        }
+        assert_eq!(sent, vec![u16::MAX]);
+
+        assert_eq!(blocks(&sender.push_block(b"a", now)), vec![1]);
+    }
+}
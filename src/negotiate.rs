@@ -0,0 +1,587 @@
+//! Option negotiation as described by RFC 2347.
+//!
+//! A client appends the options it would like to use to its request. The
+//! server answers with an `Oack` that contains only the options it agreed
+//! to, possibly with adjusted values. Options the server does not understand
+//! are left out of the `Oack`, and if no option is acknowledged at all the
+//! server carries on as if none had been requested.
+
+use std::time::Duration;
+
+use crate::packet::{
+    Code, Options, Rollover, Strictness, DEFAULT_BLOCK_SIZE, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE,
+};
+use crate::{Error, Result};
+
+/// The name of the block size option (RFC 2348).
+pub const BLKSIZE: &str = "blksize";
+
+/// The name of the timeout interval option (RFC 2349).
+pub const TIMEOUT: &str = "timeout";
+
+/// The name of the transfer size option (RFC 2349).
+pub const TSIZE: &str = "tsize";
+
+/// How long to wait for the peer before retransmitting, unless negotiated
+/// otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many times a packet is retransmitted before giving up on the peer.
+pub const DEFAULT_RETRIES: u32 = 5;
+
+/// The name of the window size option (RFC 7440).
+pub const WINDOWSIZE: &str = "windowsize";
+
+/// The name of the block number rollover option, which is not part of any
+/// RFC but widely supported.
+pub const ROLLOVER: &str = "rollover";
+
+/// The largest window a server agrees to unless configured otherwise, as
+/// every block in a window is kept in memory until it is acknowledged.
+pub const DEFAULT_MAX_WINDOW_SIZE: u16 = 64;
+
+/// The parameters of a single transfer that both peers agreed upon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TransferOptions {
+    /// The number of bytes carried by every `Data` packet but the last.
+    pub block_size: usize,
+
+    /// How long to wait for the peer before retransmitting.
+    pub timeout: Duration,
+
+    /// How many times in a row to retransmit before giving up on the peer.
+    pub retries: u32,
+
+    /// The size of the file being transferred, if it is known.
+    pub transfer_size: Option<u64>,
+
+    /// The number of blocks sent before an acknowledgement is expected.
+    pub window_size: u16,
+
+    /// What block numbers wrap around to after block 65535.
+    pub rollover: Rollover,
+
+    /// How strictly the peer's packets are parsed. This is not negotiated.
+    pub strictness: Strictness,
+
+    /// Receiving more than this many bytes ends the transfer with a "disk
+    /// full" error. This is not negotiated.
+    pub max_size: Option<u64>,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            block_size: DEFAULT_BLOCK_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            transfer_size: None,
+            window_size: 1,
+            rollover: Rollover::Zero,
+            strictness: Strictness::Strict,
+            max_size: None,
+        }
+    }
+}
+
+/// The bounds a server places on the options it accepts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Larger block sizes are clamped to this value.
+    pub max_block_size: usize,
+
+    /// Write requests announcing a larger file are refused, and uploads that
+    /// grow larger are ended.
+    pub max_transfer_size: Option<u64>,
+
+    /// Larger window sizes are clamped to this value.
+    pub max_window_size: u16,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_block_size: MAX_BLOCK_SIZE,
+            max_transfer_size: None,
+            max_window_size: DEFAULT_MAX_WINDOW_SIZE,
+        }
+    }
+}
+
+/// The kind of request the server is negotiating options for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Request {
+    /// The client reads a file whose size may be known to the server.
+    Read {
+        /// The size of the requested file.
+        file_size: Option<u64>,
+    },
+
+    /// The client writes a file to the server.
+    Write,
+}
+
+/// Decides which of the options requested by a client the server will use.
+///
+/// Starting from the server's `base` parameters, returns the options to
+/// acknowledge alongside the resulting parameters of the transfer, or the
+/// error to answer the request with if it cannot be served under these
+/// options.
+pub fn server_accept(
+    requested: &Options,
+    base: TransferOptions,
+    limits: &Limits,
+    request: Request,
+) -> Result<(Options, TransferOptions)> {
+    let mut accepted = Options::new();
+    let mut transfer = base;
+
+    for (name, value) in requested.iter() {
+        let value = match name.to_ascii_lowercase().as_str() {
+            BLKSIZE => parse_block_size(value).map(|size| {
+                transfer.block_size = size.min(limits.max_block_size);
+                transfer.block_size.to_string()
+            }),
+            TIMEOUT => parse_timeout(value).map(|timeout| {
+                transfer.timeout = timeout;
+                value.to_string()
+            }),
+            TSIZE => match (request, value.parse::<u64>()) {
+                // A read request asks for the size of the file, which can
+                // only be answered if it is known up front.
+                (Request::Read { file_size }, Ok(_)) => file_size.map(|size| {
+                    transfer.transfer_size = Some(size);
+                    size.to_string()
+                }),
+                (Request::Write, Ok(size)) => {
+                    if limits.max_transfer_size.is_some_and(|max| size > max) {
+                        return Err(Error::protocol(Code::DiskFull));
+                    }
+
+                    transfer.transfer_size = Some(size);
+                    Some(value.to_string())
+                }
+                (_, Err(_)) => None,
+            },
+            WINDOWSIZE => parse_window_size(value).map(|size| {
+                transfer.window_size = size.min(limits.max_window_size);
+                transfer.window_size.to_string()
+            }),
+            ROLLOVER => parse_rollover(value).map(|rollover| {
+                transfer.rollover = rollover;
+                value.to_string()
+            }),
+            _ => None,
+        };
+
+        if let Some(value) = value {
+            accepted.insert(name, value);
+        }
+    }
+
+    Ok((accepted, transfer))
+}
+
+/// Validates the options a server acknowledged and produces the resulting
+/// parameters of the transfer, starting from the client's `base` parameters.
+///
+/// A server may only acknowledge options the client asked for, it may only
+/// ever lower the requested block and window sizes and it must not alter the
+/// requested timeout or rollover.
+///
+/// Block numbers only wrap around to 1 if the server acknowledged it; any
+/// other server wraps around to 0, as is the custom.
+pub fn client_accept(
+    requested: &Options,
+    acknowledged: &Options,
+    base: TransferOptions,
+) -> Result<TransferOptions> {
+    let mut transfer = TransferOptions {
+        rollover: Rollover::Zero,
+        ..base
+    };
+
+    for (name, value) in acknowledged.iter() {
+        let wanted = match requested.get(name) {
+            Some(wanted) => wanted,
+            None => {
+                return Err(invalid(format!(
+                    "server acknowledged an option that was not requested: {}",
+                    name
+                )))
+            }
+        };
+
+        if name.eq_ignore_ascii_case(BLKSIZE) {
+            transfer.block_size = match (parse_block_size(value), parse_block_size(wanted)) {
+                (Some(size), Some(wanted)) if size <= wanted => size,
+                _ => return Err(invalid(format!("unacceptable block size: {}", value))),
+            };
+        } else if name.eq_ignore_ascii_case(TIMEOUT) {
+            transfer.timeout = match parse_timeout(value) {
+                Some(timeout) if value == wanted => timeout,
+                _ => return Err(invalid(format!("unacceptable timeout: {}", value))),
+            };
+        } else if name.eq_ignore_ascii_case(TSIZE) {
+            transfer.transfer_size = match value.parse() {
+                Ok(size) => Some(size),
+                Err(_) => return Err(invalid(format!("unacceptable transfer size: {}", value))),
+            };
+        } else if name.eq_ignore_ascii_case(WINDOWSIZE) {
+            transfer.window_size = match (parse_window_size(value), parse_window_size(wanted)) {
+                (Some(size), Some(wanted)) if size <= wanted => size,
+                _ => return Err(invalid(format!("unacceptable window size: {}", value))),
+            };
+        } else if name.eq_ignore_ascii_case(ROLLOVER) {
+            transfer.rollover = match parse_rollover(value) {
+                Some(rollover) if value == wanted => rollover,
+                _ => return Err(invalid(format!("unacceptable rollover: {}", value))),
+            };
+        }
+    }
+
+    Ok(transfer)
+}
+
+fn parse_block_size(value: &str) -> Option<usize> {
+    value
+        .parse()
+        .ok()
+        .filter(|size| (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(size))
+}
+
+/// Window sizes range from 1 to 65535 blocks.
+fn parse_window_size(value: &str) -> Option<u16> {
+    value.parse().ok().filter(|size| *size > 0)
+}
+
+/// Block numbers roll over to either 0 or 1.
+fn parse_rollover(value: &str) -> Option<Rollover> {
+    match value {
+        "0" => Some(Rollover::Zero),
+        "1" => Some(Rollover::One),
+        _ => None,
+    }
+}
+
+/// Timeouts are given in whole seconds, from 1 to 255.
+pub fn parse_timeout(value: &str) -> Option<Duration> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .map(|secs| Duration::from_secs(u64::from(secs)))
+}
+
+fn invalid(message: String) -> Error {
+    Error::Protocol {
+        code: Code::OptionNegotiation,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_ignores_unknown_options() {
+        let mut requested = Options::new();
+        requested.insert("x-vendor", "1");
+
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            Request::Write,
+        )
+        .unwrap();
+        assert!(accepted.is_empty());
+        assert_eq!(transfer, TransferOptions::default());
+    }
+
+    #[test]
+    fn test_server_block_size() {
+        let limits = Limits {
+            max_block_size: 1468,
+            ..Limits::default()
+        };
+
+        let mut requested = Options::new();
+        requested.insert("BLKSIZE", "1024");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(BLKSIZE), Some("1024"));
+        assert_eq!(transfer.block_size, 1024);
+
+        requested.insert(BLKSIZE, "65464");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(BLKSIZE), Some("1468"));
+        assert_eq!(transfer.block_size, 1468);
+
+        for invalid in &["7", "65465", "potato"] {
+            requested.insert(BLKSIZE, invalid);
+            let (accepted, transfer) = server_accept(
+                &requested,
+                TransferOptions::default(),
+                &limits,
+                Request::Write,
+            )
+            .unwrap();
+            assert!(accepted.is_empty());
+            assert_eq!(transfer.block_size, DEFAULT_BLOCK_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_server_timeout() {
+        let mut requested = Options::new();
+        requested.insert(TIMEOUT, "3");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(TIMEOUT), Some("3"));
+        assert_eq!(transfer.timeout, Duration::from_secs(3));
+
+        for invalid in &["0", "256", "1.5"] {
+            requested.insert(TIMEOUT, invalid);
+            let (accepted, transfer) = server_accept(
+                &requested,
+                TransferOptions::default(),
+                &Limits::default(),
+                Request::Write,
+            )
+            .unwrap();
+            assert!(accepted.is_empty());
+            assert_eq!(transfer.timeout, DEFAULT_TIMEOUT);
+        }
+    }
+
+    #[test]
+    fn test_server_transfer_size() {
+        let mut requested = Options::new();
+        requested.insert(TSIZE, "0");
+
+        let request = Request::Read {
+            file_size: Some(1234),
+        };
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            request,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(TSIZE), Some("1234"));
+        assert_eq!(transfer.transfer_size, Some(1234));
+
+        let request = Request::Read { file_size: None };
+        let (accepted, _) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            request,
+        )
+        .unwrap();
+        assert!(accepted.is_empty());
+
+        let limits = Limits {
+            max_transfer_size: Some(4096),
+            ..Limits::default()
+        };
+
+        requested.insert(TSIZE, "4096");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(TSIZE), Some("4096"));
+        assert_eq!(transfer.transfer_size, Some(4096));
+
+        requested.insert(TSIZE, "4097");
+        let error = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap_err();
+        assert_eq!(error.code(), Code::DiskFull);
+    }
+
+    #[test]
+    fn test_server_window_size() {
+        let limits = Limits {
+            max_window_size: 16,
+            ..Limits::default()
+        };
+
+        let mut requested = Options::new();
+        requested.insert(WINDOWSIZE, "8");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(WINDOWSIZE), Some("8"));
+        assert_eq!(transfer.window_size, 8);
+
+        requested.insert(WINDOWSIZE, "65535");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &limits,
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(WINDOWSIZE), Some("16"));
+        assert_eq!(transfer.window_size, 16);
+
+        for invalid in &["0", "65536", "-1"] {
+            requested.insert(WINDOWSIZE, invalid);
+            let (accepted, transfer) = server_accept(
+                &requested,
+                TransferOptions::default(),
+                &limits,
+                Request::Write,
+            )
+            .unwrap();
+            assert!(accepted.is_empty());
+            assert_eq!(transfer.window_size, 1);
+        }
+    }
+
+    #[test]
+    fn test_server_rollover() {
+        let mut requested = Options::new();
+        requested.insert(ROLLOVER, "1");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            Request::Write,
+        )
+        .unwrap();
+        assert_eq!(accepted.get(ROLLOVER), Some("1"));
+        assert_eq!(transfer.rollover, Rollover::One);
+
+        requested.insert(ROLLOVER, "2");
+        let (accepted, transfer) = server_accept(
+            &requested,
+            TransferOptions::default(),
+            &Limits::default(),
+            Request::Write,
+        )
+        .unwrap();
+        assert!(accepted.is_empty());
+        assert_eq!(transfer.rollover, Rollover::Zero);
+    }
+
+    #[test]
+    fn test_client_rejects_unrequested_options() {
+        let mut requested = Options::new();
+        requested.insert("x-vendor", "1");
+
+        let mut acknowledged = Options::new();
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_ok());
+
+        acknowledged.insert("X-VENDOR", "1");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_ok());
+
+        acknowledged.insert("x-other", "1");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_client_block_size() {
+        let mut requested = Options::new();
+        requested.insert(BLKSIZE, "1468");
+
+        let mut acknowledged = Options::new();
+        acknowledged.insert(BLKSIZE, "1024");
+        let transfer =
+            client_accept(&requested, &acknowledged, TransferOptions::default()).unwrap();
+        assert_eq!(transfer.block_size, 1024);
+
+        acknowledged.insert(BLKSIZE, "2048");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+
+        acknowledged.insert(BLKSIZE, "4");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_client_timeout_and_transfer_size() {
+        let mut requested = Options::new();
+        requested.insert(TIMEOUT, "2");
+        requested.insert(TSIZE, "0");
+
+        let mut acknowledged = Options::new();
+        acknowledged.insert(TIMEOUT, "2");
+        acknowledged.insert(TSIZE, "1234");
+        let transfer =
+            client_accept(&requested, &acknowledged, TransferOptions::default()).unwrap();
+        assert_eq!(transfer.timeout, Duration::from_secs(2));
+        assert_eq!(transfer.transfer_size, Some(1234));
+
+        acknowledged.insert(TIMEOUT, "5");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_client_window_size() {
+        let mut requested = Options::new();
+        requested.insert(WINDOWSIZE, "8");
+
+        let mut acknowledged = Options::new();
+        acknowledged.insert(WINDOWSIZE, "4");
+        let transfer =
+            client_accept(&requested, &acknowledged, TransferOptions::default()).unwrap();
+        assert_eq!(transfer.window_size, 4);
+
+        acknowledged.insert(WINDOWSIZE, "16");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_client_rollover() {
+        let mut requested = Options::new();
+        requested.insert(ROLLOVER, "1");
+
+        let mut acknowledged = Options::new();
+        acknowledged.insert(ROLLOVER, "1");
+        let transfer =
+            client_accept(&requested, &acknowledged, TransferOptions::default()).unwrap();
+        assert_eq!(transfer.rollover, Rollover::One);
+
+        acknowledged.insert(ROLLOVER, "0");
+        assert!(client_accept(&requested, &acknowledged, TransferOptions::default()).is_err());
+        // A server that leaves the option out wraps around to 0.
+        let base = TransferOptions {
+            rollover: Rollover::One,
+            ..TransferOptions::default()
+        };
+        let transfer = client_accept(&requested, &Options::new(), base).unwrap();
+        assert_eq!(transfer.rollover, Rollover::Zero);
+    }
+}
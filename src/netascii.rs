@@ -0,0 +1,350 @@
+//! Translation to and from netascii, the line-oriented text format that
+//! TFTP transfers in `NetAscii` mode (RFC 1350, RFC 764).
+//!
+//! In netascii every line ends in CR LF, and a carriage return that is not
+//! part of a line ending is followed by a NUL byte. Lines end in a bare LF
+//! locally.
+
+#[cfg(feature = "async")]
+use std::io::ErrorKind;
+use std::io::{Read, Result, Write};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::task::{ready, Context, Poll};
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+const NUL: u8 = b'\0';
+
+/// Encodes the bytes read from a reader as netascii.
+///
+/// Every LF becomes CR LF and every CR becomes CR NUL.
+#[derive(Debug)]
+pub struct Encoder<R> {
+    inner: R,
+    raw: Vec<u8>,
+    /* the second half of a translation that did not fit into the last read */
+    pending: Option<u8>,
+}
+
+impl<R> Encoder<R> {
+    /// Creates an encoder that reads from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            raw: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Unwraps this encoder, returning the underlying reader.
+    ///
+    /// The second half of a line ending that was split across two reads is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Encodes the first `bytes_read` bytes of the scratch buffer into `buf`
+    /// and returns the number of bytes written to it.
+    fn encode(&mut self, bytes_read: usize, buf: &mut [u8]) -> usize {
+        let mut written = 0;
+        for &byte in &self.raw[..bytes_read] {
+            let (first, second) = match byte {
+                LF => (CR, Some(LF)),
+                CR => (CR, Some(NUL)),
+                byte => (byte, None),
+            };
+
+            buf[written] = first;
+            written += 1;
+
+            if let Some(second) = second {
+                if written < buf.len() {
+                    buf[written] = second;
+                    written += 1;
+                } else {
+                    self.pending = Some(second);
+                }
+            }
+        }
+
+        written
+    }
+}
+
+impl<R: Read> Read for Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(byte) = self.pending.take() {
+            buf[0] = byte;
+            return Ok(1);
+        }
+
+        // Every byte that is read may take up two bytes once encoded.
+        self.raw.resize((buf.len() / 2).max(1), 0);
+        let bytes_read = self.inner.read(&mut self.raw)?;
+
+        Ok(self.encode(bytes_read, buf))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin> AsyncRead for Encoder<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        if let Some(byte) = this.pending.take() {
+            buf.put_slice(&[byte]);
+            return Poll::Ready(Ok(()));
+        }
+
+        this.raw.resize((buf.remaining() / 2).max(1), 0);
+        let bytes_read = {
+            let mut raw = ReadBuf::new(&mut this.raw);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw))?;
+            raw.filled().len()
+        };
+
+        let written = this.encode(bytes_read, buf.initialize_unfilled());
+        buf.advance(written);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Decodes netascii, passing the result on to a writer.
+///
+/// Every CR LF becomes LF and every CR NUL becomes CR. A carriage return at
+/// the end of one write is held back until the next write shows what it is
+/// followed by, so `finish` must be called once all data has been written.
+#[derive(Debug)]
+pub struct Decoder<W> {
+    inner: W,
+    decoded: Vec<u8>,
+    /* how much of `decoded` has been passed on to an asynchronous writer */
+    passed_on: usize,
+    /* whether the last byte written was a CR */
+    carriage_return: bool,
+}
+
+impl<W> Decoder<W> {
+    /// Creates a decoder that writes to `inner`.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            decoded: Vec::new(),
+            passed_on: 0,
+            carriage_return: false,
+        }
+    }
+
+    /// Decodes `buf` into the scratch buffer.
+    fn decode(&mut self, buf: &[u8]) {
+        self.decoded.clear();
+        self.passed_on = 0;
+
+        for &byte in buf {
+            if self.carriage_return {
+                self.carriage_return = false;
+
+                match byte {
+                    LF => {
+                        self.decoded.push(LF);
+                        continue;
+                    }
+                    NUL => {
+                        self.decoded.push(CR);
+                        continue;
+                    }
+                    /* not valid netascii, so the CR is kept as it is */
+                    _ => self.decoded.push(CR),
+                }
+            }
+
+            if byte == CR {
+                self.carriage_return = true;
+            } else {
+                self.decoded.push(byte);
+            }
+        }
+    }
+}
+
+impl<W: Write> Decoder<W> {
+    /// Writes out a carriage return that was held back and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        if self.carriage_return {
+            self.inner.write_all(&[CR])?;
+        }
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.decode(buf);
+        self.inner.write_all(&self.decoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<W: AsyncWrite + Unpin> Decoder<W> {
+    /// Writes out a carriage return that was held back and returns the
+    /// underlying writer, like `finish` does for a blocking writer.
+    pub async fn finish_async(mut self) -> Result<W> {
+        use tokio::io::AsyncWriteExt;
+
+        self.flush().await?;
+        if self.carriage_return {
+            self.inner.write_all(&[CR]).await?;
+            self.inner.flush().await?;
+        }
+
+        Ok(self.inner)
+    }
+
+    /// Passes on what is left of the last write.
+    fn poll_pass_on(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.passed_on < self.decoded.len() {
+            let remaining = &self.decoded[self.passed_on..];
+            match ready!(Pin::new(&mut self.inner).poll_write(cx, remaining))? {
+                0 => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                n => self.passed_on += n,
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A write is accepted as soon as it is decoded, and passed on to the
+/// underlying writer by the next call to `poll_write` or `poll_flush`.
+///
+/// `poll_shutdown` does not write out a carriage return that was held back;
+/// see `finish_async`.
+#[cfg(feature = "async")]
+impl<W: AsyncWrite + Unpin> AsyncWrite for Decoder<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pass_on(cx))?;
+        this.decode(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pass_on(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pass_on(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(input: &[u8], chunk: usize) -> Vec<u8> {
+        let mut encoder = Encoder::new(input);
+        let mut output = Vec::new();
+        let mut buf = vec![0; chunk];
+
+        loop {
+            match encoder.read(&mut buf).unwrap() {
+                0 => break,
+                n => output.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        output
+    }
+
+    fn decode(input: &[u8], chunk: usize) -> Vec<u8> {
+        let mut decoder = Decoder::new(Vec::new());
+        for chunk in input.chunks(chunk) {
+            decoder.write_all(chunk).unwrap();
+        }
+        decoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_encode() {
+        let expected = b"one\r\ntwo\r\0three\r\n";
+        assert_eq!(&encode(b"one\ntwo\rthree\n", 512)[..], &expected[..]);
+
+        // Translations straddle the boundary between reads.
+        for chunk in 1..8 {
+            assert_eq!(&encode(b"one\ntwo\rthree\n", chunk)[..], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let expected = b"one\ntwo\rthree\n\r";
+        let input = b"one\r\ntwo\r\0three\r\n\r";
+        assert_eq!(&decode(input, 512)[..], &expected[..]);
+
+        // Translations straddle the boundary between writes.
+        for chunk in 1..8 {
+            assert_eq!(&decode(input, chunk)[..], &expected[..]);
+        }
+
+        // A bare CR is kept.
+        assert_eq!(&decode(b"a\rb", 512)[..], b"a\rb");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = b"\r\n\n\r\r\0\ra\nb";
+        let encoded = encode(input, 3);
+        assert_eq!(&decode(&encoded, 5)[..], &input[..]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_round_trip() {
+        // Slices and vectors are both blocking and asynchronous, so the
+        // traits are named explicitly.
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let input = b"one\ntwo\rthree\r";
+
+        let mut encoded = Vec::new();
+        let mut encoder = Encoder::new(&input[..]);
+        AsyncReadExt::read_to_end(&mut encoder, &mut encoded)
+            .await
+            .unwrap();
+        assert_eq!(&encoded[..], b"one\r\ntwo\r\0three\r\0");
+
+        let mut decoder = Decoder::new(Vec::new());
+        for chunk in encoded.chunks(3) {
+            AsyncWriteExt::write_all(&mut decoder, chunk).await.unwrap();
+        }
+        assert_eq!(&decoder.finish_async().await.unwrap()[..], &input[..]);
+    }
+}
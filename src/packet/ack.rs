@@ -1,13 +1,14 @@
 //! An `Ack` packet is a receipt for a successfully transmitted
 //! block.
 
-use std::io::{self, ErrorKind, Result};
-use std::mem::size_of;
+use std::fmt;
 
 use super::Block;
 use crate::bytes::{FromBytes, IntoBytes};
 use crate::packet::opcode::Opcode;
 use crate::packet::sealed::Packet;
+use crate::packet::AnyPacket;
+use crate::Result;
 
 /// An acknowledgement that a `Data` packet has been received successfully.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -23,24 +24,28 @@ impl Ack {
     }
 }
 
+impl fmt::Display for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} block={}", Opcode::Ack, self.block)
+    }
+}
+
 impl Packet for Ack {
     const OPCODE: Opcode = Opcode::Ack;
+
+    fn from_any(packet: AnyPacket) -> std::result::Result<crate::packet::Packet<Self>, AnyPacket> {
+        match packet {
+            AnyPacket::Ack(ack) => Ok(ack),
+            packet => Err(packet),
+        }
+    }
 }
 
 impl FromBytes for Ack {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
-        let bytes = bytes.as_ref();
-
-        let split_at = size_of::<Block>();
-
-        if bytes.len() != split_at {
-            return Err(ErrorKind::InvalidInput.into());
-        }
-
-        let block = &bytes[..split_at];
-        let block = Block::from_bytes(block)?;
+        let block = Block::from_bytes(bytes)?;
 
         Ok(Self { block })
     }
@@ -62,8 +67,8 @@ mod tests {
         let actual = Ack::from_bytes(&input[..]).unwrap();
 
         assert_eq!(actual.block.0, 1);
-        assert!(Ack::from_bytes(&[1]).is_err());
-        assert!(Ack::from_bytes(&[1, 2, 3]).is_err());
+        assert!(Ack::from_bytes([1]).is_err());
+        assert!(Ack::from_bytes([1, 2, 3]).is_err());
     }
 
     #[test]
@@ -0,0 +1,262 @@
+//! A packet of any type, for when the type of a packet is not known until it
+//! has been parsed.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem::size_of;
+
+use super::{sealed, Ack, Data, Error, Hexdump, Oack, Opcode, Packet, Rrq, Strictness, Wrq};
+use crate::bytes::{Bytes, FromBytes, IntoBytes};
+use crate::Result;
+
+/// A TFTP packet of any type.
+///
+/// Parsing dispatches on the opcode, so whatever arrived can be matched on
+/// without guessing its type first.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AnyPacket {
+    /// A read request.
+    Rrq(Packet<Rrq>),
+
+    /// A write request.
+    Wrq(Packet<Wrq>),
+
+    /// A block of a file.
+    Data(Packet<Data>),
+
+    /// An acknowledgement of a block.
+    Ack(Packet<Ack>),
+
+    /// An error that ends a transfer.
+    Error(Packet<Error>),
+
+    /// An acknowledgement of requested options (RFC 2347).
+    Oack(Packet<Oack>),
+
+    /// A packet with an opcode this crate does not know about.
+    Raw {
+        /// The opcode of the packet.
+        opcode: u16,
+
+        /// Everything that follows the opcode.
+        body: Vec<u8>,
+    },
+}
+
+impl AnyPacket {
+    /// Parses a packet of any type.
+    ///
+    /// A packet with an unknown opcode is kept as `Raw`, but a packet with a
+    /// known opcode has to be well-formed.
+    pub fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
+        Self::from_bytes_with(bytes, Strictness::Strict)
+    }
+
+    /// Parses a packet of any type, tolerating the known deviations of some
+    /// peers if `strictness` is `Strictness::Lenient`.
+    pub fn from_bytes_with<T: AsRef<[u8]>>(bytes: T, strictness: Strictness) -> Result<Self> {
+        let bytes = bytes.as_ref();
+
+        let split_at = size_of::<u16>();
+        if bytes.len() < split_at {
+            return Err(crate::Error::parse(bytes.len(), "truncated packet"));
+        }
+
+        let (header, body) = bytes.split_at(split_at);
+        let opcode = Bytes::<u16>::from_bytes(header)?.into_inner();
+
+        Ok(match Opcode::from_u16(opcode) {
+            Ok(Opcode::Rrq) => AnyPacket::Rrq(Packet::parse(bytes, strictness)?),
+            Ok(Opcode::Wrq) => AnyPacket::Wrq(Packet::parse(bytes, strictness)?),
+            Ok(Opcode::Data) => AnyPacket::Data(Packet::parse(bytes, strictness)?),
+            Ok(Opcode::Ack) => AnyPacket::Ack(Packet::parse(bytes, strictness)?),
+            Ok(Opcode::Error) => AnyPacket::Error(Packet::parse(bytes, strictness)?),
+            Ok(Opcode::Oack) => AnyPacket::Oack(Packet::parse(bytes, strictness)?),
+            Err(_) => AnyPacket::Raw {
+                opcode,
+                body: body.to_vec(),
+            },
+        })
+    }
+
+    /// Encodes the packet for sending.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            AnyPacket::Rrq(packet) => packet.into_bytes(),
+            AnyPacket::Wrq(packet) => packet.into_bytes(),
+            AnyPacket::Data(packet) => packet.into_bytes(),
+            AnyPacket::Ack(packet) => packet.into_bytes(),
+            AnyPacket::Error(packet) => packet.into_bytes(),
+            AnyPacket::Oack(packet) => packet.into_bytes(),
+            AnyPacket::Raw { opcode, mut body } => {
+                let mut bytes = Bytes::new(opcode).into_bytes();
+                bytes.append(&mut body);
+                bytes
+            }
+        }
+    }
+
+    /// The opcode that identifies the type of this packet, unless it is one
+    /// this crate does not know about.
+    pub fn opcode(&self) -> Option<Opcode> {
+        match self {
+            AnyPacket::Rrq(packet) => Some(packet.header),
+            AnyPacket::Wrq(packet) => Some(packet.header),
+            AnyPacket::Data(packet) => Some(packet.header),
+            AnyPacket::Ack(packet) => Some(packet.header),
+            AnyPacket::Error(packet) => Some(packet.header),
+            AnyPacket::Oack(packet) => Some(packet.header),
+            AnyPacket::Raw { .. } => None,
+        }
+    }
+}
+
+/// A one-line summary of the packet, like that of `Packet`. A packet with an
+/// unknown opcode is summarized as `UNKNOWN opcode=9 len=2`.
+///
+/// The alternate form, `{:#}`, follows the summary with a hexdump of the
+/// encoded packet.
+impl fmt::Display for AnyPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyPacket::Rrq(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Wrq(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Data(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Ack(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Error(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Oack(packet) => write!(f, "{}", packet.body)?,
+            AnyPacket::Raw { opcode, body } => {
+                write!(f, "UNKNOWN opcode={} len={}", opcode, body.len())?
+            }
+        }
+        if f.alternate() {
+            write!(f, "\n{}", Hexdump::new(&self.clone().into_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: sealed::Packet> TryFrom<AnyPacket> for Packet<T> {
+    type Error = AnyPacket;
+
+    fn try_from(packet: AnyPacket) -> std::result::Result<Self, AnyPacket> {
+        T::from_any(packet)
+    }
+}
+
+macro_rules! impl_from_packet {
+    ($($variant:ident),*) => {
+        $(
+            impl From<Packet<$variant>> for AnyPacket {
+                fn from(packet: Packet<$variant>) -> Self {
+                    AnyPacket::$variant(packet)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_packet!(Rrq, Wrq, Data, Ack, Error, Oack);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Block, Code, Mode, Options};
+
+    #[test]
+    fn test_from_bytes() {
+        let packets: Vec<AnyPacket> = vec![
+            Packet::rrq("file", Mode::Octet).into(),
+            Packet::wrq("file", Mode::NetAscii).into(),
+            Packet::data(Block::new(1), b"data").into(),
+            Packet::ack(Block::new(1)).into(),
+            Packet::error(Code::DiskFull, "full").into(),
+            Packet::oack(Options::new()).into(),
+        ];
+
+        for packet in packets {
+            let bytes = packet.clone().into_bytes();
+            assert_eq!(AnyPacket::from_bytes(bytes).unwrap(), packet);
+        }
+
+        assert!(AnyPacket::from_bytes([]).is_err());
+        assert!(AnyPacket::from_bytes([0]).is_err());
+        assert!(AnyPacket::from_bytes([0, 4, 0]).is_err());
+    }
+
+    #[test]
+    fn test_raw() {
+        let bytes = [0, 9, b'h', b'i'];
+        let packet = AnyPacket::from_bytes(bytes).unwrap();
+        assert_eq!(
+            packet,
+            AnyPacket::Raw {
+                opcode: 9,
+                body: b"hi".to_vec()
+            }
+        );
+        assert_eq!(packet.opcode(), None);
+        assert_eq!(packet.into_bytes(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut options = Options::new();
+        options.insert("blksize", "1468");
+        let packets: Vec<(AnyPacket, &str)> = vec![
+            (
+                Packet::rrq_with_options("pxelinux.0", Mode::Octet, options.clone()).into(),
+                r#"RRQ "pxelinux.0" octet blksize=1468"#,
+            ),
+            (
+                Packet::try_wrq(b"caf\xe9", Mode::NetAscii, Options::new())
+                    .unwrap()
+                    .into(),
+                "WRQ \"caf\u{fffd}\" netascii",
+            ),
+            (
+                Packet::data(Block::new(42), vec![0; 512]).into(),
+                "DATA block=42 len=512",
+            ),
+            (Packet::ack(Block::new(7)).into(), "ACK block=7"),
+            (
+                Packet::error(Code::FileNotFound, "no \"such\" file").into(),
+                r#"ERROR code=1 "no \"such\" file""#,
+            ),
+            (Packet::oack(options).into(), "OACK blksize=1468"),
+            (Packet::oack(Options::new()).into(), "OACK"),
+            (
+                AnyPacket::Raw {
+                    opcode: 9,
+                    body: b"hi".to_vec(),
+                },
+                "UNKNOWN opcode=9 len=2",
+            ),
+        ];
+
+        for (packet, expected) in packets {
+            assert_eq!(packet.to_string(), expected);
+        }
+
+        let ack = Packet::ack(Block::new(7));
+        assert_eq!(ack.to_string(), "ACK block=7");
+        assert_eq!(
+            format!("{:#}", ack),
+            format!("ACK block=7\n{}", Hexdump::new(&[0, 4, 0, 7]))
+        );
+        assert_eq!(
+            format!("{:#}", AnyPacket::from(ack.clone())),
+            format!("{:#}", ack)
+        );
+    }
+
+    #[test]
+    fn test_try_from() {
+        let packet = AnyPacket::from(Packet::ack(Block::new(3)));
+        assert_eq!(
+            Packet::<Ack>::try_from(packet.clone()),
+            Ok(Packet::ack(Block::new(3)))
+        );
+        assert_eq!(Packet::<Data>::try_from(packet.clone()), Err(packet));
+    }
+}
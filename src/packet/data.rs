@@ -1,17 +1,19 @@
 //! A `Data` packet encapsulates a block of data.
 //!
-//! If a `Data` block contains less than 512 bytes as its payload,
-//! then it is the final `Data` block to be sent.
+//! If a `Data` block contains less than the negotiated block size (512 bytes
+//! by default) as its payload, then it is the final `Data` block to be sent.
 
-use std::io::{self, ErrorKind, Result};
+use std::fmt;
 use std::mem::size_of;
 
-use super::Block;
+use super::{Block, MAX_BLOCK_SIZE};
 use crate::bytes::{FromBytes, IntoBytes};
 use crate::packet::opcode::Opcode;
 use crate::packet::sealed::Packet;
+use crate::packet::AnyPacket;
+use crate::Result;
 
-/// A vehicle for transmitting up to 512 bytes of a file.
+/// A vehicle for transmitting one block of a file.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Data {
     /// The identifier for this data.
@@ -31,22 +33,48 @@ impl Data {
     }
 }
 
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} block={} len={}",
+            Opcode::Data,
+            self.block,
+            self.data.len()
+        )
+    }
+}
+
 impl Packet for Data {
     const OPCODE: Opcode = Opcode::Data;
+
+    fn from_any(packet: AnyPacket) -> std::result::Result<crate::packet::Packet<Self>, AnyPacket> {
+        match packet {
+            AnyPacket::Data(data) => Ok(data),
+            packet => Err(packet),
+        }
+    }
 }
 
 impl FromBytes for Data {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
 
         let split_at = size_of::<Block>();
         if split_at > bytes.len() {
-            return Err(ErrorKind::InvalidInput.into());
+            return Err(crate::Error::parse(bytes.len(), "truncated packet"));
         }
 
         let (block, data) = bytes.split_at(split_at);
+        if data.len() > MAX_BLOCK_SIZE {
+            return Err(crate::Error::parse(
+                split_at + MAX_BLOCK_SIZE,
+                "block too large",
+            ));
+        }
+
         let block = Block::from_bytes(block)?;
         let data = data.to_vec();
 
@@ -70,7 +98,7 @@ mod tests {
 
     #[test]
     fn test_from_bytes() {
-        let input = vec![0x00, 0x01, b'p', b'o', b't', b'a', b't', b'o'];
+        let input = [0x00, 0x01, b'p', b'o', b't', b'a', b't', b'o'];
         let actual = Data::from_bytes(&input[..]).unwrap();
 
         assert_eq!(actual.block, Block(1));
@@ -82,7 +110,13 @@ mod tests {
         assert_eq!(actual.block, Block(2));
         assert_eq!(actual.data, &[]);
 
-        assert!(Data::from_bytes(&[0]).is_err());
+        assert!(Data::from_bytes([0]).is_err());
+
+        let input = vec![0; 2 + MAX_BLOCK_SIZE];
+        assert!(Data::from_bytes(&input[..]).is_ok());
+
+        let input = vec![0; 2 + MAX_BLOCK_SIZE + 1];
+        assert!(Data::from_bytes(&input[..]).is_err());
     }
 
     #[test]
@@ -2,41 +2,67 @@
 
 use std::convert::AsRef;
 use std::fmt;
-use std::io::{self, ErrorKind, Result};
 use std::mem::size_of;
+use std::str;
 
-use crate::bytes::{Bytes, FromBytes, IntoBytes};
+use crate::bytes::{Bytes, FirstNul, FromBytes, IntoBytes};
 use crate::packet::opcode::Opcode;
 use crate::packet::sealed::Packet;
+use crate::packet::{AnyPacket, Strictness};
+use crate::Result;
 
-/// Error codes defined by RFC 1350.
+/// Error codes defined by RFC 1350 and RFC 2347.
 #[allow(missing_docs)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Code {
-    NotDefined = 0,
-    FileNotFound = 1,
-    AccessViolation = 2,
-    DiskFull = 3,
-    IllegalOperation = 4,
-    UnknownTid = 5,
-    FileAlreadyExists = 6,
-    NoSuchUser = 7,
+    NotDefined,
+    FileNotFound,
+    AccessViolation,
+    DiskFull,
+    IllegalOperation,
+    UnknownTid,
+    FileAlreadyExists,
+    NoSuchUser,
+
+    /// The transfer is ended because of the options (RFC 2347).
+    OptionNegotiation,
+
+    /// A code that is not defined by any RFC, such as a vendor extension.
+    Other(u16),
 }
 
 impl Code {
-    /// Attempts to convert a `u16` into an error `Code`.
-    pub fn from_u16(val: u16) -> Result<Self> {
-        Ok(match val {
-            v if v == 0 => Code::NotDefined,
-            v if v == 1 => Code::FileNotFound,
-            v if v == 2 => Code::AccessViolation,
-            v if v == 3 => Code::DiskFull,
-            v if v == 4 => Code::IllegalOperation,
-            v if v == 5 => Code::UnknownTid,
-            v if v == 6 => Code::FileAlreadyExists,
-            v if v == 7 => Code::NoSuchUser,
-            _ => return Err(ErrorKind::InvalidInput.into()),
-        })
+    /// Converts a `u16` into an error `Code`, keeping codes it does not
+    /// know as `Other`.
+    pub fn from_u16(val: u16) -> Self {
+        match val {
+            0 => Code::NotDefined,
+            1 => Code::FileNotFound,
+            2 => Code::AccessViolation,
+            3 => Code::DiskFull,
+            4 => Code::IllegalOperation,
+            5 => Code::UnknownTid,
+            6 => Code::FileAlreadyExists,
+            7 => Code::NoSuchUser,
+            8 => Code::OptionNegotiation,
+            val => Code::Other(val),
+        }
+    }
+
+    /// The value of this `Code` as it appears in an `Error` packet.
+    pub fn as_u16(self) -> u16 {
+        match self {
+            Code::NotDefined => 0,
+            Code::FileNotFound => 1,
+            Code::AccessViolation => 2,
+            Code::DiskFull => 3,
+            Code::IllegalOperation => 4,
+            Code::UnknownTid => 5,
+            Code::FileAlreadyExists => 6,
+            Code::NoSuchUser => 7,
+            Code::OptionNegotiation => 8,
+            Code::Other(val) => val,
+        }
     }
 
     /// Converts `Code` into its string representation.
@@ -50,31 +76,35 @@ impl Code {
             Code::UnknownTid => "Unknown transfer ID",
             Code::FileAlreadyExists => "File already exists",
             Code::NoSuchUser => "No such user",
+            Code::OptionNegotiation => "Option negotiation failed",
+            Code::Other(_) => "Unknown error",
         }
     }
 }
 
 impl IntoBytes for Code {
     fn into_bytes(self) -> Vec<u8> {
-        let val = self as u16;
+        let val = self.as_u16();
         let bytes = Bytes::new(val);
         bytes.into_bytes()
     }
 }
 
 impl FromBytes for Code {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = Bytes::from_bytes(bytes)?;
-        let code = Code::from_u16(bytes.into_inner())?;
-        Ok(code)
+        Ok(Code::from_u16(bytes.into_inner()))
     }
 }
 
 impl fmt::Display for Code {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.as_str())
+        match self {
+            Code::Other(val) => write!(f, "{} ({})", self.as_str(), val),
+            _ => write!(f, "{}", self.as_str()),
+        }
     }
 }
 
@@ -98,18 +128,78 @@ impl Error {
     }
 }
 
+/// Shows the code by its number, since the message usually describes it.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} code={} {:?}",
+            Opcode::Error,
+            self.code.as_u16(),
+            self.message
+        )
+    }
+}
+
 impl Packet for Error {
     const OPCODE: Opcode = Opcode::Error;
+
+    fn parse_body(body: &[u8], strictness: Strictness) -> Result<Self> {
+        match Self::from_bytes(body) {
+            Err(_) if strictness == Strictness::Lenient => (),
+            result => return result,
+        }
+
+        // Leniently, the message ends at its nul terminator if it has one,
+        // and at the end of the packet otherwise.
+        let split_at = size_of::<u16>();
+        if body.len() < split_at {
+            return Err(crate::Error::parse(body.len(), "truncated packet"));
+        }
+
+        let (code, message) = body.split_at(split_at);
+        let code = Code::from_bytes(code)?;
+        let message = match message.first_nul_idx() {
+            Some(nul) => {
+                log::warn!(
+                    "ignoring {} trailing bytes of an error",
+                    message.len() - nul - 1
+                );
+                &message[..nul]
+            }
+            None => {
+                log::warn!("accepting an error message that lacks its nul terminator");
+                message
+            }
+        };
+        let message = str::from_utf8(message)
+            .map_err(|e| crate::Error::parse(split_at + e.valid_up_to(), "invalid UTF-8"))?;
+
+        Ok(Self::new(code, message))
+    }
+
+    fn from_any(packet: AnyPacket) -> std::result::Result<crate::packet::Packet<Self>, AnyPacket> {
+        match packet {
+            AnyPacket::Error(error) => Ok(error),
+            packet => Err(packet),
+        }
+    }
 }
 
 impl FromBytes for Error {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
-        let (code, message) = bytes.split_at(size_of::<u16>());
+
+        let split_at = size_of::<u16>();
+        if bytes.len() < split_at {
+            return Err(crate::Error::parse(bytes.len(), "truncated packet"));
+        }
+
+        let (code, message) = bytes.split_at(split_at);
         let code = Code::from_bytes(code)?;
-        let message = Bytes::from_bytes(message)?;
+        let message = Bytes::<String>::from_bytes(message).map_err(|e| e.offset_by(split_at))?;
         let message = message.into_inner();
 
         Ok(Self { code, message })
@@ -131,15 +221,20 @@ mod tests {
 
     #[test]
     fn test_error_code_conversions() {
-        assert_eq!(Code::from_u16(0).unwrap(), Code::NotDefined);
-        assert_eq!(Code::from_u16(1).unwrap(), Code::FileNotFound);
-        assert_eq!(Code::from_u16(2).unwrap(), Code::AccessViolation);
-        assert_eq!(Code::from_u16(3).unwrap(), Code::DiskFull);
-        assert_eq!(Code::from_u16(4).unwrap(), Code::IllegalOperation);
-        assert_eq!(Code::from_u16(5).unwrap(), Code::UnknownTid);
-        assert_eq!(Code::from_u16(6).unwrap(), Code::FileAlreadyExists);
-        assert_eq!(Code::from_u16(7).unwrap(), Code::NoSuchUser);
-        assert!(Code::from_u16(8).is_err());
+        assert_eq!(Code::from_u16(0), Code::NotDefined);
+        assert_eq!(Code::from_u16(1), Code::FileNotFound);
+        assert_eq!(Code::from_u16(2), Code::AccessViolation);
+        assert_eq!(Code::from_u16(3), Code::DiskFull);
+        assert_eq!(Code::from_u16(4), Code::IllegalOperation);
+        assert_eq!(Code::from_u16(5), Code::UnknownTid);
+        assert_eq!(Code::from_u16(6), Code::FileAlreadyExists);
+        assert_eq!(Code::from_u16(7), Code::NoSuchUser);
+        assert_eq!(Code::from_u16(8), Code::OptionNegotiation);
+        assert_eq!(Code::from_u16(0x4242), Code::Other(0x4242));
+
+        for val in 0..=u16::MAX {
+            assert_eq!(Code::from_u16(val).as_u16(), val);
+        }
     }
 
     #[test]
@@ -155,8 +250,40 @@ mod tests {
         assert_eq!(actual.code, Code::NotDefined);
         assert_eq!(actual.message.as_str(), "");
 
-        assert!(Error::from_bytes(&[0, 1]).is_err());
-        assert!(Error::from_bytes(&[2, b'\0']).is_err());
+        let input = &[0, 8, b'\0'];
+        let actual = Error::from_bytes(&input[..]).unwrap();
+        assert_eq!(actual.code, Code::OptionNegotiation);
+
+        let input = &[0x42, 0x42, b'v', b'\0'];
+        let actual = Error::from_bytes(&input[..]).unwrap();
+        assert_eq!(actual.code, Code::Other(0x4242));
+        assert_eq!(actual.clone().into_bytes(), &input[..]);
+
+        assert!(Error::from_bytes([0, 1]).is_err());
+        assert!(Error::from_bytes([2, b'\0']).is_err());
+        assert!(Error::from_bytes([0]).is_err());
+        assert!(Error::from_bytes([]).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient() {
+        let lenient = |bytes: &[u8]| Error::parse_body(bytes, Strictness::Lenient);
+
+        assert_eq!(
+            lenient(b"\x00\x01missing").unwrap(),
+            Error::new(Code::FileNotFound, "missing")
+        );
+        assert_eq!(
+            lenient(b"\x00\x02denied\0\0\0").unwrap(),
+            Error::new(Code::AccessViolation, "denied")
+        );
+        assert_eq!(
+            lenient(b"\x00\x03").unwrap(),
+            Error::new(Code::DiskFull, "")
+        );
+
+        assert!(lenient(b"\x00").is_err());
+        assert!(lenient(b"\x00\x01\xff").is_err());
     }
 
     #[test]
@@ -0,0 +1,82 @@
+//! A hexdump of raw bytes, for inspecting packets byte by byte.
+
+use std::fmt;
+
+/// The number of bytes shown on every line.
+const LINE_LEN: usize = 16;
+
+/// Shows bytes in the canonical layout of `hexdump -C`: an offset, sixteen
+/// bytes in hex and the same bytes as ASCII, with anything unprintable shown
+/// as a dot.
+///
+/// ```
+/// use tftp::packet::Hexdump;
+///
+/// let dump = Hexdump::new(b"\x00\x04\x00\x2a");
+/// assert_eq!(
+///     dump.to_string(),
+///     "00000000  00 04 00 2a                                       |...*|"
+/// );
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Hexdump<'a>(&'a [u8]);
+
+impl<'a> Hexdump<'a> {
+    /// Creates a hexdump of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+}
+
+/// Lines are separated by newlines, without one after the last line.
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, line) in self.0.chunks(LINE_LEN).enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{:08x} ", idx * LINE_LEN)?;
+            for col in 0..LINE_LEN {
+                /* an extra space halfway through */
+                if col % 8 == 0 {
+                    write!(f, " ")?;
+                }
+                match line.get(col) {
+                    Some(byte) => write!(f, "{:02x} ", byte)?,
+                    None => write!(f, "   ")?,
+                }
+            }
+
+            write!(f, " |")?;
+            for &byte in line {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            write!(f, "|")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Hexdump::new(b"").to_string(), "");
+
+        let dump = Hexdump::new(b"\x00\x03\x00\x01Alice was beginning to get very tired");
+        let expected = "\
+00000000  00 03 00 01 41 6c 69 63  65 20 77 61 73 20 62 65  |....Alice was be|
+00000010  67 69 6e 6e 69 6e 67 20  74 6f 20 67 65 74 20 76  |ginning to get v|
+00000020  65 72 79 20 74 69 72 65  64                       |ery tired|";
+        assert_eq!(dump.to_string(), expected);
+    }
+}
@@ -1,37 +1,72 @@
 //! Parsing and creating TFTP packets.
 
 use std::convert::AsRef;
-use std::io::{self, ErrorKind, Result};
+use std::fmt;
+use std::io::ErrorKind;
 use std::mem::size_of;
 
 use crate::bytes::{Bytes, FromBytes, IntoBytes};
+use crate::Result;
 pub use ack::Ack;
+pub use any::AnyPacket;
 pub use data::Data;
 pub use error::{Code, Error};
+pub use hexdump::Hexdump;
 pub use mode::Mode;
+pub use oack::Oack;
 pub use opcode::Opcode;
+pub use options::Options;
+pub(crate) use rq::Rq;
 pub use rq::{Rrq, Wrq};
+pub use view::{DataRef, RqRef};
 
 mod ack;
+mod any;
 mod data;
 mod error;
 pub mod expect;
+mod hexdump;
 mod mode;
+mod oack;
 mod opcode;
+mod options;
 mod rq;
+mod view;
 
-/// The maximum number of bytes carried in a `Data` packet.
-pub const MAX_PAYLOAD_SIZE: usize = 512;
+/// The number of bytes carried in a `Data` packet unless a different block
+/// size is negotiated.
+pub const DEFAULT_BLOCK_SIZE: usize = 512;
 
-/// The total size of a TFTP packet. (512 + 2 byte opcode + 2 byte block ID)
-pub const MAX_PACKET_SIZE: usize = 516;
+/// The smallest block size that may be negotiated (RFC 2348).
+pub const MIN_BLOCK_SIZE: usize = 8;
 
-mod sealed {
+/// The largest block size that may be negotiated (RFC 2348).
+pub const MAX_BLOCK_SIZE: usize = 65464;
+
+/// The size of the header that precedes a `Data` payload. (2 byte opcode +
+/// 2 byte block ID)
+pub const DATA_HEADER_SIZE: usize = 4;
+
+/// The total size of the largest possible TFTP packet.
+pub const MAX_PACKET_SIZE: usize = MAX_BLOCK_SIZE + DATA_HEADER_SIZE;
+
+pub(crate) mod sealed {
     use crate::bytes::{FromBytes, IntoBytes};
     use crate::packet::opcode::Opcode;
+    use crate::packet::{AnyPacket, Strictness};
 
-    pub trait Packet: FromBytes + IntoBytes {
+    pub trait Packet: FromBytes<Error = crate::Error> + IntoBytes {
         const OPCODE: Opcode;
+
+        /// Parses the body of a packet of this type. Only the types that
+        /// have known deviations to tolerate look at `strictness`.
+        fn parse_body(body: &[u8], _strictness: Strictness) -> Result<Self, crate::Error> {
+            Self::from_bytes(body)
+        }
+
+        /// Unwraps a packet of this type, or gives back a packet of any
+        /// other type.
+        fn from_any(packet: AnyPacket) -> Result<super::Packet<Self>, AnyPacket>;
     }
 }
 
@@ -44,10 +79,75 @@ impl Block {
     pub fn new(val: u16) -> Self {
         Self(val)
     }
+
+    /// The identifier of the block that follows this one.
+    pub fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+
+    /// Returns `true` if this block comes before `other`.
+    ///
+    /// Block numbers wrap around, so a block is considered to come before
+    /// the 32767 blocks that follow it.
+    pub fn precedes(self, other: Block) -> bool {
+        let distance = other.0.wrapping_sub(self.0);
+        distance != 0 && distance < 0x8000
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What block numbers wrap around to once block 65535 has been sent, which
+/// happens to files of more than 65535 blocks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Rollover {
+    /// Block 65535 is followed by block 0. Most implementations do this.
+    #[default]
+    Zero,
+
+    /// Block 65535 is followed by block 1.
+    One,
+}
+
+impl Rollover {
+    /// The identifier of the `index`th block of a transfer, where the first
+    /// `Data` block has an index of 1.
+    ///
+    /// An index of 0 identifies the `Ack` that accepts a write request.
+    pub fn block(self, index: u64) -> Block {
+        match self {
+            Rollover::Zero => Block(index as u16),
+            Rollover::One if index == 0 => Block(0),
+            Rollover::One => Block(((index - 1) % u64::from(u16::MAX)) as u16 + 1),
+        }
+    }
+}
+
+/// How strictly the packets that arrive from a peer are parsed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Strictness {
+    /// Packets have to be well-formed, which suits conformance testing.
+    #[default]
+    Strict,
+
+    /// A few deviations that some clients, embedded bootloaders among them,
+    /// are known for are accepted and logged:
+    ///
+    /// - a request whose mode lacks its nul terminator,
+    /// - trailing bytes after the mode or options of a request,
+    /// - an error message that lacks its nul terminator or is followed by
+    ///   trailing bytes.
+    ///
+    /// Anything that parses strictly parses the same way leniently.
+    Lenient,
 }
 
 impl FromBytes for Block {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
@@ -85,20 +185,66 @@ impl<T: sealed::Packet> Packet<T> {
 
 impl Packet<Rrq> {
     /// Creates a new read request packet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
     pub fn rrq<T: AsRef<str>>(filename: T, mode: Mode) -> Self {
         let rrq = Rrq::new(filename, mode);
 
         Self::new(rrq)
     }
+
+    /// Creates a new read request packet that requests the given options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
+    pub fn rrq_with_options<T: AsRef<str>>(filename: T, mode: Mode, options: Options) -> Self {
+        let rrq = Rrq::with_options(filename, mode, options);
+
+        Self::new(rrq)
+    }
+
+    /// Creates a new read request packet for a filename that need not be
+    /// valid UTF-8, or fails if it contains a nul byte.
+    pub fn try_rrq<T: AsRef<[u8]>>(filename: T, mode: Mode, options: Options) -> Result<Self> {
+        let rrq = Rrq::try_with_options(filename, mode, options)?;
+
+        Ok(Self::new(rrq))
+    }
 }
 
 impl Packet<Wrq> {
     /// Creates a new write request packet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
     pub fn wrq<T: AsRef<str>>(filename: T, mode: Mode) -> Self {
         let wrq = Wrq::new(filename, mode);
 
         Self::new(wrq)
     }
+
+    /// Creates a new write request packet that requests the given options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
+    pub fn wrq_with_options<T: AsRef<str>>(filename: T, mode: Mode, options: Options) -> Self {
+        let wrq = Wrq::with_options(filename, mode, options);
+
+        Self::new(wrq)
+    }
+
+    /// Creates a new write request packet for a filename that need not be
+    /// valid UTF-8, or fails if it contains a nul byte.
+    pub fn try_wrq<T: AsRef<[u8]>>(filename: T, mode: Mode, options: Options) -> Result<Self> {
+        let wrq = Wrq::try_with_options(filename, mode, options)?;
+
+        Ok(Self::new(wrq))
+    }
 }
 
 impl Packet<Data> {
@@ -128,6 +274,15 @@ impl Packet<Error> {
     }
 }
 
+impl Packet<Oack> {
+    /// Creates a new option acknowledgement packet.
+    pub fn oack(options: Options) -> Self {
+        let oack = Oack::new(options);
+
+        Self::new(oack)
+    }
+}
+
 impl From<ErrorKind> for Code {
     fn from(kind: ErrorKind) -> Self {
         match kind {
@@ -139,41 +294,29 @@ impl From<ErrorKind> for Code {
     }
 }
 
-impl From<io::Error> for Packet<Error> {
-    fn from(err: io::Error) -> Packet<Error> {
-        let code = err.kind().into();
-        let message = format!("{}", code);
-        Packet::error(code, message)
-    }
-}
-
-impl From<Packet<Error>> for io::Error {
-    fn from(err: Packet<Error>) -> io::Error {
-        let kind = match err.body.code {
-            Code::FileNotFound => ErrorKind::NotFound,
-            Code::AccessViolation => ErrorKind::PermissionDenied,
-            Code::FileAlreadyExists => ErrorKind::AlreadyExists,
-            _ => ErrorKind::Other,
-        };
+impl<T: sealed::Packet> FromBytes for Packet<T> {
+    type Error = crate::Error;
 
-        io::Error::new(kind, err.body.message)
+    fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<Self> {
+        Self::parse(bytes.as_ref(), Strictness::Strict)
     }
 }
 
-impl<T: sealed::Packet> FromBytes for Packet<T> {
-    type Error = io::Error;
+impl<T: sealed::Packet> Packet<T> {
+    /// Parses a packet of this type, opcode included.
+    pub(crate) fn parse(bytes: &[u8], strictness: Strictness) -> Result<Self> {
+        let split_at = size_of::<u16>();
+        if bytes.len() < split_at {
+            return Err(crate::Error::parse(bytes.len(), "truncated packet"));
+        }
 
-    fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<Self> {
-        let bytes = bytes.as_ref();
-        let (header, body) = bytes.split_at(size_of::<u16>());
+        let (header, body) = bytes.split_at(split_at);
         let opcode = Opcode::from_bytes(header)?;
         if opcode != T::OPCODE {
-            return Err(ErrorKind::InvalidData.into());
+            return Err(crate::Error::parse(0, "unexpected opcode"));
         }
 
-        /* FIXME: Remove map_err and just use `?` */
-        let body =
-            T::from_bytes(body).map_err(|_| -> io::Error { ErrorKind::InvalidData.into() })?;
+        let body = T::parse_body(body, strictness).map_err(|e| e.offset_by(split_at))?;
 
         Ok(Self {
             header: opcode,
@@ -182,6 +325,20 @@ impl<T: sealed::Packet> FromBytes for Packet<T> {
     }
 }
 
+/// A one-line summary of the packet, such as `DATA block=42 len=512`.
+///
+/// The alternate form, `{:#}`, follows the summary with a hexdump of the
+/// encoded packet.
+impl<T: sealed::Packet + Clone + fmt::Display> fmt::Display for Packet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.body)?;
+        if f.alternate() {
+            write!(f, "\n{}", Hexdump::new(&self.clone().into_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
 impl<T: sealed::Packet> IntoBytes for Packet<T> {
     fn into_bytes(self) -> Vec<u8> {
         let mut body = self.body.into_bytes();
@@ -195,6 +352,28 @@ impl<T: sealed::Packet> IntoBytes for Packet<T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_block_precedes() {
+        assert!(Block::new(1).precedes(Block::new(2)));
+        assert!(!Block::new(2).precedes(Block::new(1)));
+        assert!(!Block::new(2).precedes(Block::new(2)));
+        assert!(Block::new(u16::MAX).precedes(Block::new(0)));
+        assert!(!Block::new(0).precedes(Block::new(u16::MAX)));
+    }
+
+    #[test]
+    fn test_rollover() {
+        assert_eq!(Rollover::Zero.block(1), Block::new(1));
+        assert_eq!(Rollover::Zero.block(65535), Block::new(65535));
+        assert_eq!(Rollover::Zero.block(65536), Block::new(0));
+        assert_eq!(Rollover::Zero.block(65537), Block::new(1));
+
+        assert_eq!(Rollover::One.block(0), Block::new(0));
+        assert_eq!(Rollover::One.block(65535), Block::new(65535));
+        assert_eq!(Rollover::One.block(65536), Block::new(1));
+        assert_eq!(Rollover::One.block(2 * 65535 + 1), Block::new(1));
+    }
+
     #[test]
     fn test_rrq() {
         let rrq = Packet::rrq("alice-in-wonderland.txt", Mode::NetAscii);
@@ -231,9 +410,27 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_oack() {
+        let mut options = Options::new();
+        options.insert("blksize", "1428");
+        let oack = Packet::oack(options.clone());
+        assert_eq!(oack.header, Opcode::Oack);
+
+        let op = vec![0, 6];
+        let mut opts = b"blksize\x001428\0".to_vec();
+        let mut bytes = op;
+        bytes.append(&mut opts);
+        assert_eq!(bytes, oack.into_bytes());
+
+        let expected = Packet::oack(options);
+        let actual = Packet::<Oack>::from_bytes(&bytes[..]).unwrap();
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn test_data() {
-        let data = Packet::data(Block(25), &[1, 2, 3]);
+        let data = Packet::data(Block(25), [1, 2, 3]);
         assert_eq!(data.header, Opcode::Data);
 
         let op = vec![0, 3];
@@ -244,7 +441,7 @@ mod tests {
         bytes.append(&mut dat);
         assert_eq!(bytes, data.into_bytes());
 
-        let expected = Packet::data(Block(25), &[1, 2, 3]);
+        let expected = Packet::data(Block(25), [1, 2, 3]);
         let actual = Packet::<Data>::from_bytes(&bytes[..]).unwrap();
         assert_eq!(expected, actual);
     }
@@ -282,4 +479,26 @@ mod tests {
         let actual = Packet::<Error>::from_bytes(&bytes[..]).unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_truncated() {
+        assert!(Packet::<Rrq>::from_bytes([]).is_err());
+        assert!(Packet::<Ack>::from_bytes([0]).is_err());
+        assert!(Packet::<Ack>::from_bytes([0, 4, 0]).is_err());
+        assert!(Packet::<Data>::from_bytes([0, 3, 0]).is_err());
+        assert!(Packet::<Error>::from_bytes([0, 5]).is_err());
+        assert!(Packet::<Error>::from_bytes([0, 5, 0]).is_err());
+    }
+
+    #[test]
+    fn test_parse_error_offset() {
+        let err = Packet::<Rrq>::from_bytes(b"\x00\x01file\0bogus\0").unwrap_err();
+        assert!(matches!(err, crate::Error::Parse { offset: 7, .. }));
+
+        let err = Packet::<Oack>::from_bytes(b"\x00\x06blksize\x001428").unwrap_err();
+        assert!(matches!(err, crate::Error::Parse { offset: 14, .. }));
+
+        let err = Packet::<Ack>::from_bytes([0, 3, 0, 1]).unwrap_err();
+        assert!(matches!(err, crate::Error::Parse { offset: 0, .. }));
+    }
 }
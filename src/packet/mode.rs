@@ -3,10 +3,10 @@
 //! `Mail` is deprecated and should not be implemented.
 
 use std::fmt;
-use std::io::{self, ErrorKind, Result};
 use std::str::FromStr;
 
 use crate::bytes::{Bytes, FromBytes, IntoBytes};
+use crate::Result;
 
 /// The modes of operation for TFTP.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -24,13 +24,16 @@ pub enum Mode {
 impl Mode {
     /// Produces a `String` representation of this `Mode`.
     pub fn into_string(self) -> String {
-        let s = match self {
+        self.as_str().to_string()
+    }
+
+    /// The name of this `Mode` as it appears in a request.
+    pub fn as_str(self) -> &'static str {
+        match self {
             Mode::Mail => "mail",
             Mode::NetAscii => "netascii",
             Mode::Octet => "octet",
-        };
-
-        s.to_string()
+        }
     }
 }
 
@@ -42,7 +45,7 @@ impl IntoBytes for Mode {
 }
 
 impl FromBytes for Mode {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = bytes.as_ref();
@@ -54,7 +57,7 @@ impl FromBytes for Mode {
 }
 
 impl FromStr for Mode {
-    type Err = io::Error;
+    type Err = crate::Error;
 
     fn from_str(s: &str) -> Result<Self> {
         let s = s.to_ascii_lowercase();
@@ -63,7 +66,7 @@ impl FromStr for Mode {
             "mail" => Mode::Mail,
             "netascii" => Mode::NetAscii,
             "octet" => Mode::Octet,
-            _ => return Err(ErrorKind::InvalidInput.into()),
+            _ => return Err(crate::Error::parse(0, "unknown transfer mode")),
         })
     }
 }
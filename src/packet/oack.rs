@@ -0,0 +1,84 @@
+//! An `Oack` packet acknowledges the options a peer has agreed to use for
+//! a transfer (RFC 2347).
+
+use std::fmt;
+
+use crate::bytes::{FromBytes, IntoBytes};
+use crate::packet::opcode::Opcode;
+use crate::packet::options::Options;
+use crate::packet::sealed::Packet;
+use crate::packet::AnyPacket;
+use crate::Result;
+
+/// An option acknowledgement.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Oack {
+    /// The options accepted by the responding peer.
+    pub options: Options,
+}
+
+impl Oack {
+    /// Creates a new `Oack` packet.
+    pub fn new(options: Options) -> Self {
+        Self { options }
+    }
+}
+
+impl fmt::Display for Oack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Opcode::Oack)?;
+        if !self.options.is_empty() {
+            write!(f, " {}", self.options)?;
+        }
+        Ok(())
+    }
+}
+
+impl Packet for Oack {
+    const OPCODE: Opcode = Opcode::Oack;
+
+    fn from_any(packet: AnyPacket) -> std::result::Result<crate::packet::Packet<Self>, AnyPacket> {
+        match packet {
+            AnyPacket::Oack(oack) => Ok(oack),
+            packet => Err(packet),
+        }
+    }
+}
+
+impl FromBytes for Oack {
+    type Error = crate::Error;
+
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
+        let options = Options::from_bytes(bytes)?;
+
+        Ok(Self { options })
+    }
+}
+
+impl IntoBytes for Oack {
+    fn into_bytes(self) -> Vec<u8> {
+        self.options.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        let actual = Oack::from_bytes(b"blksize\x001024\0").unwrap();
+        assert_eq!(actual.options.get("blksize"), Some("1024"));
+
+        assert!(Oack::from_bytes(b"blksize\0").is_err());
+    }
+
+    #[test]
+    fn test_into_bytes() {
+        let mut options = Options::new();
+        options.insert("blksize", "1024");
+        let oack = Oack::new(options);
+
+        assert_eq!(&oack.into_bytes()[..], b"blksize\x001024\0");
+    }
+}
@@ -2,9 +2,9 @@
 
 use std::convert::AsRef;
 use std::fmt;
-use std::io::{self, ErrorKind, Result};
 
 use crate::bytes::{Bytes, FromBytes, IntoBytes};
+use crate::Result;
 
 /// An integer identifier for the type of TFTP packet.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -24,18 +24,22 @@ pub enum Opcode {
     /// A courtesy packet to indicate the peer has experienced an error
     /// and will not complete the transmission.
     Error = 5,
+
+    /// Acknowledges the options requested by the peer (RFC 2347).
+    Oack = 6,
 }
 
 impl Opcode {
     /// Tries to produce an `Opcode` from a `u16`.
     pub fn from_u16(val: u16) -> Result<Self> {
         Ok(match val {
-            v if v == 1 => Opcode::Rrq,
-            v if v == 2 => Opcode::Wrq,
-            v if v == 3 => Opcode::Data,
-            v if v == 4 => Opcode::Ack,
-            v if v == 5 => Opcode::Error,
-            _ => return Err(ErrorKind::InvalidInput.into()),
+            1 => Opcode::Rrq,
+            2 => Opcode::Wrq,
+            3 => Opcode::Data,
+            4 => Opcode::Ack,
+            5 => Opcode::Error,
+            6 => Opcode::Oack,
+            _ => return Err(crate::Error::parse(0, "unknown opcode")),
         })
     }
 }
@@ -49,7 +53,7 @@ impl IntoBytes for Opcode {
 }
 
 impl FromBytes for Opcode {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let bytes = Bytes::from_bytes(bytes)?;
@@ -66,6 +70,7 @@ impl fmt::Display for Opcode {
             Opcode::Data => "DATA",
             Opcode::Ack => "ACK",
             Opcode::Error => "ERROR",
+            Opcode::Oack => "OACK",
         };
 
         write!(f, "{}", s)
@@ -84,9 +89,10 @@ mod tests {
         assert_eq!(Opcode::from_u16(3).unwrap(), Opcode::Data);
         assert_eq!(Opcode::from_u16(4).unwrap(), Opcode::Ack);
         assert_eq!(Opcode::from_u16(5).unwrap(), Opcode::Error);
-        assert!(Opcode::from_u16(6).is_err());
+        assert_eq!(Opcode::from_u16(6).unwrap(), Opcode::Oack);
+        assert!(Opcode::from_u16(7).is_err());
 
         assert_eq!(Opcode::Ack.into_bytes(), vec![0x00, 0x04]);
-        assert_eq!(Opcode::from_bytes(&[0x00, 0x01]).unwrap(), Opcode::Rrq);
+        assert_eq!(Opcode::from_bytes([0x00, 0x01]).unwrap(), Opcode::Rrq);
     }
 }
@@ -0,0 +1,236 @@
+//! Describes the options that may be appended to a request or carried by an
+//! `Oack` packet, as defined by RFC 2347.
+//!
+//! Options are transmitted as a sequence of `name\0value\0` pairs. Option
+//! names are case-insensitive and their order is preserved.
+
+use std::convert::AsRef;
+use std::fmt;
+
+use super::Strictness;
+use crate::bytes::{Bytes, FirstNul, FromBytes, IntoBytes};
+use crate::Result;
+
+/// An ordered list of `name=value` options.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Options(Vec<(String, String)>);
+
+impl Options {
+    /// Creates an empty list of options.
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Appends an option to the list, replacing the value of any existing
+    /// option with the same name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` contains a nul byte.
+    pub fn insert<N: AsRef<str>, V: AsRef<str>>(&mut self, name: N, value: V) {
+        self.try_insert(name, value)
+            .expect("option contains a nul byte")
+    }
+
+    /// Appends an option to the list like `insert`, or fails if `name` or
+    /// `value` contains a nul byte, which would split it in two on the wire.
+    pub fn try_insert<N: AsRef<str>, V: AsRef<str>>(&mut self, name: N, value: V) -> Result<()> {
+        let name = name.as_ref();
+        let value = value.as_ref();
+        if name.contains('\0') || value.contains('\0') {
+            return Err(crate::Error::InvalidOption);
+        }
+        let value = value.to_string();
+
+        match self
+            .0
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some((_, v)) => *v = value,
+            None => self.0.push((name.to_string(), value)),
+        }
+        Ok(())
+    }
+
+    /// Looks up the value of an option by its (case-insensitive) name.
+    pub fn get<N: AsRef<str>>(&self, name: N) -> Option<&str> {
+        let name = name.as_ref();
+
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns `true` if an option with the given name is present.
+    pub fn contains<N: AsRef<str>>(&self, name: N) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Iterates over the options in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// The number of options in the list.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if there are no options in the list.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Options {
+    /// Parses the options of a request. Leniently, the options are cut short
+    /// at the first pair that is not well-formed.
+    pub(crate) fn from_bytes_with(bytes: &[u8], strictness: Strictness) -> Result<Self> {
+        match Self::from_bytes(bytes) {
+            Err(_) if strictness == Strictness::Lenient => (),
+            result => return result,
+        }
+
+        let string = |bytes: &[u8]| -> Option<(String, usize)> {
+            let len = bytes.first_nul_idx()? + 1;
+            let s = Bytes::<String>::from_bytes(&bytes[..len]).ok()?;
+            Some((s.into_inner(), len))
+        };
+
+        let mut options = Options::new();
+        let mut offset = 0;
+        while let Some((name, name_len)) = string(&bytes[offset..]) {
+            let (value, value_len) = match string(&bytes[offset + name_len..]) {
+                Some(value) => value,
+                None => break,
+            };
+            options.0.push((name, value));
+            offset += name_len + value_len;
+        }
+
+        log::warn!(
+            "ignoring {} trailing bytes of a request",
+            bytes.len() - offset
+        );
+        Ok(options)
+    }
+}
+
+/// Shows the options as space-separated `name=value` pairs.
+impl fmt::Display for Options {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (name, value)) in self.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromBytes for Options {
+    type Error = crate::Error;
+
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
+        let bytes = bytes.as_ref();
+        let mut strings = Vec::new();
+
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            let split_at = match rest.first_nul_idx() {
+                Some(idx) => idx + 1,
+                None => return Err(crate::Error::parse(bytes.len(), "missing nul terminator")),
+            };
+
+            let s =
+                Bytes::<String>::from_bytes(&rest[..split_at]).map_err(|e| e.offset_by(offset))?;
+            strings.push(s.into_inner());
+            offset += split_at;
+        }
+
+        /* every option name must be followed by a value */
+        if strings.len() % 2 != 0 {
+            return Err(crate::Error::parse(bytes.len(), "option without a value"));
+        }
+
+        let mut options = Options::new();
+        let mut strings = strings.into_iter();
+        while let (Some(name), Some(value)) = (strings.next(), strings.next()) {
+            options.0.push((name, value));
+        }
+
+        Ok(options)
+    }
+}
+
+impl IntoBytes for Options {
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for (name, value) in self.0 {
+            bytes.append(&mut Bytes::new(name).into_bytes());
+            bytes.append(&mut Bytes::new(value).into_bytes());
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes() {
+        let actual = Options::from_bytes(b"blksize\x001428\0tsize\x000\0").unwrap();
+        let pairs: Vec<_> = actual.iter().collect();
+        assert_eq!(pairs, vec![("blksize", "1428"), ("tsize", "0")]);
+        assert_eq!(actual.get("BLKSIZE"), Some("1428"));
+
+        assert!(Options::from_bytes(b"").unwrap().is_empty());
+        assert!(Options::from_bytes(b"blksize\0").is_err());
+        assert!(Options::from_bytes(b"blksize\x001428").is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient() {
+        let input = b"blksize\x001468\0\xff\0garbage";
+        assert!(Options::from_bytes_with(input, Strictness::Strict).is_err());
+
+        let options = Options::from_bytes_with(input, Strictness::Lenient).unwrap();
+        let options: Vec<_> = options.iter().collect();
+        assert_eq!(options, vec![("blksize", "1468")]);
+
+        let input = b"tsize\x000\0\0";
+        let options = Options::from_bytes_with(input, Strictness::Lenient).unwrap();
+        assert_eq!(options.get("tsize"), Some("0"));
+        assert_eq!(options.len(), 1);
+    }
+
+    #[test]
+    fn test_into_bytes() {
+        let mut options = Options::new();
+        options.insert("blksize", "1428");
+        options.insert("tsize", "0");
+        options.insert("BlkSize", "512");
+
+        assert_eq!(options.len(), 2);
+        assert_eq!(&options.into_bytes()[..], b"blksize\x00512\0tsize\x000\0");
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut options = Options::new();
+        assert!(options.try_insert("blksize", "1428").is_ok());
+
+        let err = options.try_insert("blk\0size", "1428").unwrap_err();
+        assert!(matches!(err, crate::Error::InvalidOption));
+        let err = options.try_insert("blksize", "14\x0028").unwrap_err();
+        assert!(matches!(err, crate::Error::InvalidOption));
+        assert_eq!(options.get("blksize"), Some("1428"));
+    }
+}
@@ -3,10 +3,16 @@
 //! This module is meant to be specialized by submodules and
 //! therefore it is not meant to be used directly.
 
-use std::io::{self, ErrorKind, Result};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::str;
 
 use super::mode::Mode;
+use super::options::Options;
+use super::Strictness;
 use crate::bytes::{Bytes, FirstNul, FromBytes, IntoBytes};
+use crate::Result;
 
 mod rrq;
 mod wrq;
@@ -16,29 +22,118 @@ pub use wrq::Wrq;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Rq {
-    pub filename: String,
+    /// The name of the requested file, as it was sent.
+    ///
+    /// RFC 1350 leaves the encoding of filenames to the host, so it need not
+    /// be valid UTF-8.
+    pub filename: Vec<u8>,
     pub mode: Mode,
+    pub options: Options,
 }
 
-impl FromBytes for Rq {
-    type Error = io::Error;
+impl Rq {
+    /// Creates a request, or fails if `filename` contains a nul byte, which
+    /// would end it early on the wire.
+    pub fn new<T: AsRef<[u8]>>(filename: T, mode: Mode, options: Options) -> Result<Self> {
+        let filename = filename.as_ref();
+        if filename.first_nul_idx().is_some() {
+            return Err(crate::Error::InvalidFilename);
+        }
+
+        Ok(Self {
+            filename: filename.to_vec(),
+            mode,
+            options,
+        })
+    }
 
-    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
-        let bytes = bytes.as_ref();
+    /// The filename, if it is valid UTF-8.
+    pub fn filename_str(&self) -> Option<&str> {
+        str::from_utf8(&self.filename).ok()
+    }
+
+    /// The filename, with anything that is not valid UTF-8 replaced by
+    /// `U+FFFD`.
+    pub fn filename_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.filename)
+    }
 
+    /// The filename as a path component.
+    ///
+    /// On Unix any bytes make up a path, elsewhere the filename has to be
+    /// valid UTF-8.
+    pub fn filename_os(&self) -> Option<&OsStr> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            Some(OsStr::from_bytes(&self.filename))
+        }
+
+        #[cfg(not(unix))]
+        {
+            self.filename_str().map(OsStr::new)
+        }
+    }
+}
+
+impl Rq {
+    /// Parses a request. Leniently, a mode without its nul terminator and
+    /// trailing bytes after the mode or options are accepted.
+    pub(crate) fn from_bytes_with(bytes: &[u8], strictness: Strictness) -> Result<Self> {
         let first_nul = match bytes.first_nul_idx() {
             Some(idx) => idx,
-            None => return Err(ErrorKind::InvalidInput.into()),
+            None => return Err(crate::Error::parse(bytes.len(), "missing nul terminator")),
         };
 
         /* want to include the nul byte of the filename in its slice */
-        let split_at = first_nul + 1;
-        let (filename, mode) = bytes.split_at(split_at);
-        let filename = Bytes::from_bytes(filename)?;
-        let filename = filename.into_inner();
-        let mode = Mode::from_bytes(mode)?;
+        let filename_len = first_nul + 1;
+        let (filename, rest) = bytes.split_at(filename_len);
+        let filename = Bytes::<Vec<u8>>::from_bytes(filename)?.into_inner();
+
+        let (mode, options) = match rest.first_nul_idx() {
+            Some(idx) => rest.split_at(idx + 1),
+            None if strictness == Strictness::Lenient && !rest.is_empty() => {
+                log::warn!("accepting a request whose mode lacks its nul terminator");
+                (rest, &[][..])
+            }
+            None => return Err(crate::Error::parse(bytes.len(), "missing nul terminator")),
+        };
 
-        Ok(Self { filename, mode })
+        let mode = match mode.last() {
+            Some(0) => Mode::from_bytes(mode),
+            _ => Mode::from_bytes([mode, b"\0"].concat()),
+        };
+        let mode = mode.map_err(|e| e.offset_by(filename_len))?;
+
+        let options_at = bytes.len() - options.len();
+        let options =
+            Options::from_bytes_with(options, strictness).map_err(|e| e.offset_by(options_at))?;
+
+        Ok(Self {
+            filename,
+            mode,
+            options,
+        })
+    }
+}
+
+/// Shows the filename quoted, with anything that is not valid UTF-8
+/// replaced, followed by the mode and any options.
+impl fmt::Display for Rq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.filename_lossy(), self.mode)?;
+        if !self.options.is_empty() {
+            write!(f, " {}", self.options)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromBytes for Rq {
+    type Error = crate::Error;
+
+    fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
+        Self::from_bytes_with(bytes.as_ref(), Strictness::Strict)
     }
 }
 
@@ -46,9 +141,11 @@ impl IntoBytes for Rq {
     fn into_bytes(self) -> Vec<u8> {
         let filename = Bytes::new(self.filename).into_bytes();
         let mut mode = self.mode.into_bytes();
+        let mut options = self.options.into_bytes();
 
         let mut bytes = filename;
         bytes.append(&mut mode);
+        bytes.append(&mut options);
         bytes
     }
 }
@@ -62,22 +159,83 @@ mod tests {
         let input = b"alice-in-wonderland.txt\0netascii\0";
         let actual = Rq::from_bytes(&input[..]).unwrap();
 
-        assert_eq!(actual.filename.as_str(), "alice-in-wonderland.txt");
+        assert_eq!(actual.filename_str(), Some("alice-in-wonderland.txt"));
         assert_eq!(actual.mode, Mode::NetAscii);
+        assert!(actual.options.is_empty());
+
+        let input = b"pxelinux.0\0octet\0blksize\x001468\0tsize\x000\0";
+        let actual = Rq::from_bytes(&input[..]).unwrap();
+
+        assert_eq!(actual.filename_str(), Some("pxelinux.0"));
+        assert_eq!(actual.mode, Mode::Octet);
+        let options: Vec<_> = actual.options.iter().collect();
+        assert_eq!(options, vec![("blksize", "1468"), ("tsize", "0")]);
 
         assert!(Rq::from_bytes(b"no-nul").is_err());
         assert!(Rq::from_bytes(b"only-filename-here\0").is_err());
         assert!(Rq::from_bytes(b"only-filename-here\0nonul").is_err());
+        assert!(Rq::from_bytes(b"file\0octet\0blksize\0").is_err());
+    }
+
+    #[test]
+    fn test_from_bytes_lenient() {
+        for input in &[
+            &b"pxelinux.0\0octet"[..],
+            &b"pxelinux.0\0octet\0\xff\xfe"[..],
+            &b"pxelinux.0\0octet\0blksize\x001468\0junk"[..],
+        ] {
+            assert!(Rq::from_bytes(input).is_err());
+
+            let rq = Rq::from_bytes_with(input, Strictness::Lenient).unwrap();
+            assert_eq!(rq.filename_str(), Some("pxelinux.0"));
+            assert_eq!(rq.mode, Mode::Octet);
+        }
+
+        let input = b"pxelinux.0\0octet\0blksize\x001468\0junk";
+        let rq = Rq::from_bytes_with(input, Strictness::Lenient).unwrap();
+        assert_eq!(rq.options.get("blksize"), Some("1468"));
+
+        assert!(Rq::from_bytes_with(b"pxelinux.0\0", Strictness::Lenient).is_err());
+        assert!(Rq::from_bytes_with(b"pxelinux.0\0bogus", Strictness::Lenient).is_err());
     }
 
     #[test]
     fn test_into_bytes() {
         let rq = Rq {
-            filename: "alice-in-wonderland.txt".to_string(),
+            filename: b"alice-in-wonderland.txt".to_vec(),
             mode: Mode::Octet,
+            options: Options::new(),
         };
 
         let bytes = rq.into_bytes();
         assert_eq!(&bytes[..], b"alice-in-wonderland.txt\0octet\0");
+
+        let mut options = Options::new();
+        options.insert("blksize", "1468");
+        let rq = Rq {
+            filename: b"pxelinux.0".to_vec(),
+            mode: Mode::Octet,
+            options,
+        };
+
+        let bytes = rq.into_bytes();
+        assert_eq!(&bytes[..], b"pxelinux.0\0octet\0blksize\x001468\0");
+    }
+
+    #[test]
+    fn test_raw_filename() {
+        let input = b"caf\xe9.txt\0octet\0";
+        let rq = Rq::from_bytes(&input[..]).unwrap();
+
+        assert_eq!(&rq.filename[..], b"caf\xe9.txt");
+        assert_eq!(rq.filename_str(), None);
+        assert_eq!(rq.filename_lossy(), "caf\u{fffd}.txt");
+        #[cfg(unix)]
+        assert!(rq.filename_os().is_some());
+        assert_eq!(&rq.into_bytes()[..], &input[..]);
+
+        assert!(Rq::new(b"caf\xe9.txt", Mode::Octet, Options::new()).is_ok());
+        let err = Rq::new("a\0b", Mode::Octet, Options::new()).unwrap_err();
+        assert!(matches!(err, crate::Error::InvalidFilename));
     }
 }
@@ -1,12 +1,15 @@
 //! A Write Request indicates the peer wants to transmit a file.
 
-use std::io::{self, Result};
+use std::fmt;
 
 use super::Rq;
 use crate::bytes::{FromBytes, IntoBytes};
 use crate::packet::mode::Mode;
 use crate::packet::opcode::Opcode;
+use crate::packet::options::Options;
 use crate::packet::sealed::Packet;
+use crate::packet::{AnyPacket, Strictness};
+use crate::Result;
 
 /// A write request.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -14,18 +17,58 @@ pub struct Wrq(pub Rq);
 
 impl Wrq {
     /// Creates a new `Wrq`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
     pub fn new<T: AsRef<str>>(filename: T, mode: Mode) -> Self {
-        let filename = filename.as_ref().to_string();
-        Self(Rq { filename, mode })
+        Self::with_options(filename, mode, Options::new())
+    }
+
+    /// Creates a new `Wrq` that requests the given options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `filename` contains a nul byte.
+    pub fn with_options<T: AsRef<str>>(filename: T, mode: Mode, options: Options) -> Self {
+        Self::try_with_options(filename.as_ref(), mode, options)
+            .expect("filename contains a nul byte")
+    }
+
+    /// Creates a new `Wrq` for a filename that need not be valid UTF-8, or
+    /// fails if it contains a nul byte.
+    pub fn try_with_options<T: AsRef<[u8]>>(
+        filename: T,
+        mode: Mode,
+        options: Options,
+    ) -> Result<Self> {
+        Rq::new(filename, mode, options).map(Self)
+    }
+}
+
+impl fmt::Display for Wrq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", Opcode::Wrq, self.0)
     }
 }
 
 impl Packet for Wrq {
     const OPCODE: Opcode = Opcode::Wrq;
+
+    fn parse_body(body: &[u8], strictness: Strictness) -> Result<Self> {
+        Rq::from_bytes_with(body, strictness).map(Self)
+    }
+
+    fn from_any(packet: AnyPacket) -> std::result::Result<crate::packet::Packet<Self>, AnyPacket> {
+        match packet {
+            AnyPacket::Wrq(wrq) => Ok(wrq),
+            packet => Err(packet),
+        }
+    }
 }
 
 impl FromBytes for Wrq {
-    type Error = io::Error;
+    type Error = crate::Error;
 
     fn from_bytes<T: AsRef<[u8]>>(bytes: T) -> Result<Self> {
         let rq = Rq::from_bytes(bytes)?;
@@ -0,0 +1,293 @@
+//! Borrowed views of packets, which are parsed straight from a receive buffer
+//! and encoded straight into a send buffer without allocating.
+
+use std::io::{self, ErrorKind};
+use std::mem::size_of;
+use std::str;
+
+use super::{Ack, Block, Data, Mode, Opcode, Packet, DATA_HEADER_SIZE, MAX_BLOCK_SIZE};
+use crate::bytes::FirstNul;
+use crate::{Error, Result};
+
+/// A `Data` packet whose payload is borrowed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DataRef<'a> {
+    /// The identifier for this data.
+    pub block: Block,
+
+    /// The payload.
+    pub data: &'a [u8],
+}
+
+impl<'a> DataRef<'a> {
+    /// Creates a view of a `Data` packet.
+    pub fn new(block: Block, data: &'a [u8]) -> Self {
+        Self { block, data }
+    }
+
+    /// Parses a `Data` packet, opcode included.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let body = parse_header(bytes, Opcode::Data)?;
+
+        let split_at = size_of::<Block>();
+        if body.len() < split_at {
+            return Err(Error::parse(bytes.len(), "truncated packet"));
+        }
+        if body.len() - split_at > MAX_BLOCK_SIZE {
+            return Err(Error::parse(
+                DATA_HEADER_SIZE + MAX_BLOCK_SIZE,
+                "block too large",
+            ));
+        }
+
+        let (block, data) = body.split_at(split_at);
+        let block = Block::new(u16::from_be_bytes([block[0], block[1]]));
+
+        Ok(Self { block, data })
+    }
+
+    /// The number of bytes `encode` writes.
+    pub fn encoded_len(&self) -> usize {
+        DATA_HEADER_SIZE + self.data.len()
+    }
+
+    /// Encodes the packet into the start of `buf` and returns the number of
+    /// bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.encoded_len();
+        let buf = buf.get_mut(..len).ok_or_else(too_small)?;
+
+        buf[..2].copy_from_slice(&(Opcode::Data as u16).to_be_bytes());
+        buf[2..4].copy_from_slice(&self.block.0.to_be_bytes());
+        buf[4..].copy_from_slice(self.data);
+
+        Ok(len)
+    }
+
+    /// Copies the payload into an owned packet.
+    pub fn to_packet(&self) -> Packet<Data> {
+        Packet::data(self.block, self.data)
+    }
+}
+
+impl Packet<Ack> {
+    /// Encodes the packet into the start of `buf` and returns the number of
+    /// bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        let buf = buf.get_mut(..4).ok_or_else(too_small)?;
+
+        buf[..2].copy_from_slice(&(Opcode::Ack as u16).to_be_bytes());
+        buf[2..].copy_from_slice(&self.body.block.0.to_be_bytes());
+
+        Ok(buf.len())
+    }
+}
+
+/// A read or write request whose strings are borrowed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RqRef<'a> {
+    /// Either `Opcode::Rrq` or `Opcode::Wrq`.
+    pub opcode: Opcode,
+
+    /// The name of the requested file, which need not be valid UTF-8.
+    pub filename: &'a [u8],
+
+    /// The mode of the transfer.
+    pub mode: Mode,
+
+    /* `name\0value\0` pairs, already validated */
+    options: &'a [u8],
+}
+
+impl<'a> RqRef<'a> {
+    /// Creates a view of a request without options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `opcode` is neither `Opcode::Rrq` nor `Opcode::Wrq`, or if
+    /// `filename` contains a nul byte.
+    pub fn new(opcode: Opcode, filename: &'a [u8], mode: Mode) -> Self {
+        assert!(opcode == Opcode::Rrq || opcode == Opcode::Wrq);
+        assert!(filename.first_nul_idx().is_none());
+
+        Self {
+            opcode,
+            filename,
+            mode,
+            options: &[],
+        }
+    }
+
+    /// Parses a read or write request, opcode included.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let opcode = match bytes.get(..2) {
+            Some(&[0, 1]) => Opcode::Rrq,
+            Some(&[0, 2]) => Opcode::Wrq,
+            Some(_) => return Err(Error::parse(0, "unexpected opcode")),
+            None => return Err(Error::parse(bytes.len(), "truncated packet")),
+        };
+
+        let (filename, rest) = split_bytes(bytes, 2)?;
+        let mode_at = bytes.len() - rest.len();
+        let (mode, options) = split_str(bytes, mode_at)?;
+        let mode = mode.parse().map_err(|e: Error| e.offset_by(mode_at))?;
+
+        // Validated up front so that iterating over them cannot fail.
+        let mut at = bytes.len() - options.len();
+        while at < bytes.len() {
+            let (_, rest) = split_str(bytes, at)?;
+            if rest.is_empty() {
+                return Err(Error::parse(bytes.len(), "option without a value"));
+            }
+            let (_, rest) = split_str(bytes, bytes.len() - rest.len())?;
+            at = bytes.len() - rest.len();
+        }
+
+        Ok(Self {
+            opcode,
+            filename,
+            mode,
+            options,
+        })
+    }
+
+    /// Iterates over the requested options in the order they were sent.
+    pub fn options(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let mut pairs = self.options;
+
+        std::iter::from_fn(move || {
+            if pairs.is_empty() {
+                return None;
+            }
+
+            let (name, rest) = split_str(pairs, 0).ok()?;
+            let (value, rest) = split_str(rest, 0).ok()?;
+            pairs = rest;
+            Some((name, value))
+        })
+    }
+
+    /// The number of bytes `encode` writes.
+    pub fn encoded_len(&self) -> usize {
+        let mode = self.mode.as_str();
+        2 + self.filename.len() + 1 + mode.len() + 1 + self.options.len()
+    }
+
+    /// Encodes the request into the start of `buf` and returns the number of
+    /// bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.encoded_len();
+        let mut buf = buf.get_mut(..len).ok_or_else(too_small)?;
+
+        for part in &[
+            &(self.opcode as u16).to_be_bytes()[..],
+            self.filename,
+            b"\0",
+            self.mode.as_str().as_bytes(),
+            b"\0",
+            self.options,
+        ] {
+            let (head, rest) = buf.split_at_mut(part.len());
+            head.copy_from_slice(part);
+            buf = rest;
+        }
+
+        Ok(len)
+    }
+}
+
+/// Checks the opcode of a packet and returns its body.
+fn parse_header(bytes: &[u8], opcode: Opcode) -> Result<&[u8]> {
+    match bytes.get(..2) {
+        Some(header) if header == (opcode as u16).to_be_bytes() => Ok(&bytes[2..]),
+        Some(_) => Err(Error::parse(0, "unexpected opcode")),
+        None => Err(Error::parse(bytes.len(), "truncated packet")),
+    }
+}
+
+/// Splits the nul-terminated bytes that start at `at` off `bytes`.
+fn split_bytes(bytes: &[u8], at: usize) -> Result<(&[u8], &[u8])> {
+    let rest = &bytes[at..];
+    let nul = rest
+        .first_nul_idx()
+        .ok_or_else(|| Error::parse(bytes.len(), "missing nul terminator"))?;
+
+    Ok((&rest[..nul], &rest[nul + 1..]))
+}
+
+/// Splits the nul-terminated string that starts at `at` off `bytes`.
+fn split_str(bytes: &[u8], at: usize) -> Result<(&str, &[u8])> {
+    let (s, rest) = split_bytes(bytes, at)?;
+    let s = str::from_utf8(s).map_err(|e| Error::parse(at + e.valid_up_to(), "invalid UTF-8"))?;
+
+    Ok((s, rest))
+}
+
+fn too_small() -> Error {
+    io::Error::new(ErrorKind::InvalidInput, "buffer too small for packet").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::IntoBytes;
+    use crate::packet::{Options, Rrq, Wrq};
+
+    #[test]
+    fn test_data_ref() {
+        let bytes = Packet::data(Block::new(7), b"payload").into_bytes();
+        let data = DataRef::parse(&bytes).unwrap();
+        assert_eq!(data, DataRef::new(Block::new(7), b"payload"));
+        assert_eq!(data.to_packet(), Packet::data(Block::new(7), b"payload"));
+
+        let mut buf = [0; 16];
+        assert_eq!(data.encode(&mut buf).unwrap(), bytes.len());
+        assert_eq!(&buf[..bytes.len()], &bytes[..]);
+        assert!(data.encode(&mut buf[..4]).is_err());
+
+        assert!(DataRef::parse(&[0, 3, 0]).is_err());
+        assert!(DataRef::parse(&Packet::ack(Block::new(1)).into_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_encode_ack() {
+        let ack = Packet::ack(Block::new(7));
+        let mut buf = [0; 4];
+        assert_eq!(ack.encode(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..], &ack.clone().into_bytes()[..]);
+        assert!(ack.encode(&mut buf[..3]).is_err());
+    }
+
+    #[test]
+    fn test_rq_ref() {
+        let mut options = Options::new();
+        options.insert("blksize", "1428");
+        options.insert("tsize", "0");
+        let bytes = Packet::rrq_with_options("pxelinux.0", Mode::Octet, options).into_bytes();
+
+        let rq = RqRef::parse(&bytes).unwrap();
+        assert_eq!(rq.opcode, Opcode::Rrq);
+        assert_eq!(rq.filename, b"pxelinux.0");
+        assert_eq!(rq.mode, Mode::Octet);
+        let options: Vec<_> = rq.options().collect();
+        assert_eq!(options, vec![("blksize", "1428"), ("tsize", "0")]);
+
+        let mut buf = [0; 64];
+        let len = rq.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &bytes[..]);
+
+        let wrq = RqRef::new(Opcode::Wrq, b"file", Mode::NetAscii);
+        let len = wrq.encode(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &Packet::<Wrq>::wrq("file", Mode::NetAscii).into_bytes()[..]
+        );
+        assert!(wrq.encode(&mut buf[..len - 1]).is_err());
+
+        assert!(RqRef::parse(b"\x00\x01file\0octet\0blksize\0").is_err());
+
+        let rq = RqRef::parse(b"\x00\x02caf\xe9\0octet\0").unwrap();
+        assert_eq!(rq.filename, b"caf\xe9");
+        assert!(RqRef::parse(&Packet::<Rrq>::rrq("file", Mode::Octet).into_bytes()[..3]).is_err());
+    }
+}
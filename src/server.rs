@@ -1,35 +1,163 @@
 //! A TFTP server. Implementors can use this to build a more richly-featured
 //! server application.
 
-use std::fs::OpenOptions;
-use std::io::{self, Result};
-use std::net::{ToSocketAddrs, UdpSocket};
-use std::path::{Path, PathBuf};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use rand::Rng;
 
-use crate::bytes::{FromBytes, IntoBytes};
+use crate::access::{Access, AccessPolicy, AllowAll, Direction, Glob};
+#[cfg(feature = "async")]
+use crate::asynchronous::AsyncServer;
 use crate::connection::Connection;
 use crate::connection::MIN_PORT_NUMBER;
+use crate::machine::{self, Failure, Request};
+use crate::negotiate::{Limits, TransferOptions};
+use crate::netascii;
 use crate::packet::*;
+use crate::storage::{FileSystem, Overwrite, Storage};
+use crate::Result;
 
 /// A TFTP server.
+///
+/// # Confinement
+///
+/// Clients can only read and write files within the storage the server
+/// serves files from. Requests for filenames that contain `..` or that are
+/// absolute paths are refused with an access violation, before the storage
+/// is ever asked for them. A server that serves a directory also refuses
+/// requests that lead out of it through symbolic links; see `FileSystem`.
+///
+/// # Access
+///
+/// Every request is granted, unless an `AccessPolicy` refuses it; see
+/// `Server::access_policy`.
+///
+/// # Overwriting
+///
+/// Clients cannot write over files that already exist, unless told
+/// otherwise by `Server::overwrite` or `Server::overwrite_matching`.
 pub struct Server {
     socket: UdpSocket,
-    serve_dir: PathBuf,
+    storage: Arc<dyn Storage>,
+    policy: Arc<dyn AccessPolicy>,
+    overwrite: Arc<OverwriteRules>,
+    transfer: TransferOptions,
+    limits: Limits,
 }
 
 impl Server {
     /// Creates a server configured to serve files from a given directory on
     /// a given address.
     pub fn new<A: ToSocketAddrs, P: AsRef<Path>>(bind_to: A, serve_from: P) -> Result<Self> {
+        Self::with_storage(bind_to, FileSystem::new(serve_from))
+    }
+
+    /// Creates a server configured to serve files from `storage` on a given
+    /// address.
+    pub fn with_storage<A: ToSocketAddrs, S: Storage + 'static>(
+        bind_to: A,
+        storage: S,
+    ) -> Result<Self> {
         let socket = UdpSocket::bind(bind_to)?;
         Ok(Self {
             socket,
-            serve_dir: serve_from.as_ref().to_owned(),
+            storage: Arc::new(storage),
+            policy: Arc::new(AllowAll),
+            overwrite: Arc::default(),
+            transfer: TransferOptions::default(),
+            limits: Limits::default(),
         })
     }
 
+    /// Asks `policy` about every request, with the address of the client and
+    /// the name of the file, before anything is opened. Requests it refuses
+    /// are answered with the code it refuses them with.
+    pub fn access_policy<P: AccessPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Chooses what to do when a client writes a file that already exists,
+    /// for the files that no rule of `overwrite_matching` matches. The
+    /// default is `Overwrite::Reject`.
+    pub fn overwrite(mut self, overwrite: Overwrite) -> Self {
+        Arc::make_mut(&mut self.overwrite).default = overwrite;
+        self
+    }
+
+    /// Chooses what to do when a client writes a file that already exists,
+    /// for the files whose names match `glob`. Rules are tried in the order
+    /// they were added, and the first that matches is followed.
+    pub fn overwrite_matching(mut self, glob: Glob, overwrite: Overwrite) -> Self {
+        Arc::make_mut(&mut self.overwrite)
+            .rules
+            .push((glob, overwrite));
+        self
+    }
+
+    /// Waits this long for a client before retransmitting, unless the client
+    /// negotiates (RFC 2349) a different timeout. The default is one second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.transfer.timeout = timeout;
+        self
+    }
+
+    /// Retransmits a packet at most this many times in a row before giving
+    /// up on a client. The default is 5.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.transfer.retries = retries;
+        self
+    }
+
+    /// Chooses what block numbers wrap around to in files of more than 65535
+    /// blocks, unless the client negotiates it with the `rollover` option.
+    /// The default is to wrap around to 0.
+    pub fn rollover(mut self, rollover: Rollover) -> Self {
+        self.transfer.rollover = rollover;
+        self
+    }
+
+    /// Chooses how strictly the packets of clients are parsed. The default is
+    /// `Strictness::Strict`; `Strictness::Lenient` accepts, and logs, the
+    /// malformed requests and errors that some embedded bootloaders send.
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.transfer.strictness = strictness;
+        self
+    }
+
+    /// Limits the block size (RFC 2348) the server agrees to. Clients that
+    /// ask for more are offered this size instead.
+    ///
+    /// The size is kept within the 8 to 65464 bytes allowed by the protocol.
+    pub fn max_block_size(mut self, size: usize) -> Self {
+        self.limits.max_block_size = size.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+        self
+    }
+
+    /// Limits the window size (RFC 7440) the server agrees to. Clients that
+    /// ask for more are offered this size instead.
+    ///
+    /// The default is 64 blocks, since a window's worth of blocks is held in
+    /// memory for every transfer.
+    pub fn max_window_size(mut self, size: u16) -> Self {
+        self.limits.max_window_size = size.max(1);
+        self
+    }
+
+    /// Refuses write requests that announce (RFC 2349) a file larger than
+    /// `size` bytes with a "disk full" error, and ends uploads that grow
+    /// larger than that.
+    ///
+    /// Uploads in octet mode are also ended once they grow past the size
+    /// they announced, whether or not a limit is set.
+    pub fn max_transfer_size(mut self, size: u64) -> Self {
+        self.limits.max_transfer_size = Some(size);
+        self
+    }
+
     /// Creates a server configured to serve files from a given directory on
     /// a given ip_address and a random port.
     /// On success the chosen port and the new `Server` instance are returned.
@@ -44,6 +172,27 @@ impl Server {
         Self::new(bind_to, serve_from).map(|server| (port, server))
     }
 
+    /// Turns this server into one for use on a tokio runtime, keeping its
+    /// configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime with I/O enabled.
+    #[cfg(feature = "async")]
+    pub fn into_async(self) -> Result<AsyncServer> {
+        self.socket.set_nonblocking(true)?;
+        let socket = tokio::net::UdpSocket::from_std(self.socket)?;
+
+        Ok(AsyncServer::new(
+            socket,
+            self.storage,
+            self.policy,
+            self.overwrite,
+            self.transfer,
+            self.limits,
+        ))
+    }
+
     /// Waits for requests and returns a `Handler` instance.
     ///
     /// It is intended that implementors will loop on this method and may
@@ -55,22 +204,17 @@ impl Server {
     /// can simply send the `Handler` off into the thread pool to be serviced.Ack
     /* TODO: Maybe return option instead? */
     pub fn serve(&self) -> Result<Handler> {
-        let mut buf = [0; MAX_PACKET_SIZE];
+        let mut buf = vec![0; MAX_PACKET_SIZE];
         let (nbytes, src_addr) = self.socket.recv_from(&mut buf)?;
-        let rrq = Packet::<Rrq>::from_bytes(&buf[..nbytes]);
-        let wrq = Packet::<Wrq>::from_bytes(&buf[..nbytes]);
-
-        let direction = if let Ok(rq) = rrq {
-            Direction::Get(rq)
-        } else if let Ok(wq) = wrq {
-            Direction::Put(wq)
-        } else {
-            let error = Packet::error(
-                Code::IllegalOperation,
-                format!("{}", Code::IllegalOperation),
-            );
-            let _ = self.socket.send(&error.into_bytes()[..]);
-            return Err(io::ErrorKind::InvalidInput.into());
+
+        let request = match Request::parse(&buf[..nbytes], self.transfer.strictness) {
+            Ok(request) => request,
+            Err(failure) => {
+                if let Some(ref packet) = failure.transmit {
+                    let _ = self.socket.send_to(&packet[..], src_addr);
+                }
+                return Err(failure.error);
+            }
         };
 
         let mut rng = rand::thread_rng();
@@ -78,91 +222,184 @@ impl Server {
         let addr = self.socket.local_addr()?.ip().to_string();
         let bind_to = format!("{}:{}", addr, port);
 
-        Handler::new(bind_to, src_addr, direction, self.serve_dir.clone())
+        Handler::new(bind_to, src_addr, request, self)
     }
-}
 
-enum Direction {
-    Get(Packet<Rrq>),
-    Put(Packet<Wrq>),
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
 }
 
 /// Handles a request from a single TFTP client.
 pub struct Handler {
-    socket: UdpSocket,
-    direction: Direction,
-    serve_dir: PathBuf,
+    conn: Connection,
+    client: SocketAddr,
+    request: Request,
+    storage: Arc<dyn Storage>,
+    policy: Arc<dyn AccessPolicy>,
+    overwrite: Arc<OverwriteRules>,
+    transfer: TransferOptions,
+    limits: Limits,
 }
 
 impl Handler {
-    fn new<A: ToSocketAddrs, B: ToSocketAddrs>(
+    fn new<A: ToSocketAddrs>(
         bind: A,
-        client: B,
-        direction: Direction,
-        serve_dir: PathBuf,
+        client: SocketAddr,
+        request: Request,
+        server: &Server,
     ) -> Result<Handler> {
         let socket = UdpSocket::bind(bind)?;
-        socket.connect(client)?;
 
         Ok(Handler {
-            socket,
-            direction,
-            serve_dir,
+            conn: Connection::new(socket, client),
+            client,
+            request,
+            storage: Arc::clone(&server.storage),
+            policy: Arc::clone(&server.policy),
+            overwrite: Arc::clone(&server.overwrite),
+            transfer: server.transfer,
+            limits: server.limits,
         })
     }
 
     /// Completes the handshake with the client and services the request.
     pub fn handle(self) -> Result<()> {
-        match self.direction {
-            Direction::Get(_) => self.get(),
-            Direction::Put(_) => self.put(),
+        match self.request {
+            Request::Read(ref rrq) => self.get(rrq),
+            Request::Write(ref wrq) => self.put(wrq),
         }
     }
 
-    fn get(self) -> Result<()> {
-        if let Direction::Get(rrq) = self.direction {
-            let f = match OpenOptions::new()
-                .read(true)
-                .open(self.serve_dir.join(rrq.body.0.filename))
-            {
-                Ok(f) => f,
-                Err(e) => {
-                    let error: Packet<Error> = e.into();
-                    let _ = self.socket.send(&error.clone().into_bytes()[..]);
-                    return Err(io::Error::from(error));
-                }
-            };
-            let conn = Connection::new(self.socket);
-            conn.put(f)?;
-            Ok(())
-        } else {
-            panic!("handler direction is wrong");
+    fn get(&self, rrq: &Packet<Rrq>) -> Result<()> {
+        let name = request_path(&rrq.body.0).map_err(|failure| self.conn.abort(failure))?;
+        authorize(
+            &*self.policy,
+            self.client,
+            &name,
+            &rrq.body.0,
+            Direction::Read,
+        )
+        .map_err(|failure| self.conn.abort(failure))?;
+
+        let file = self
+            .storage
+            .open_read(&name, self.client)
+            .map_err(|e| self.conn.abort(Failure::local(e)))?;
+
+        let sender = machine::accept_read(rrq, file.size(), self.transfer, &self.limits)
+            .map_err(|failure| self.conn.abort(failure))?;
+
+        match rrq.body.0.mode {
+            Mode::NetAscii => self.conn.put(sender, netascii::Encoder::new(file)),
+            _ => self.conn.put(sender, file),
         }
     }
 
-    fn put(self) -> Result<()> {
-        if let Direction::Put(wrq) = self.direction {
-            let f = match OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                /* FIXME: Not sure why this hangs if create is not specified */
-                .open(self.serve_dir.join(wrq.body.0.filename))
-            {
-                Ok(f) => f,
-                Err(e) => {
-                    let error: Packet<Error> = e.into();
-                    let _ = self.socket.send(&error.clone().into_bytes()[..]);
-                    return Err(io::Error::from(error));
-                }
-            };
-            let ack = Packet::ack(Block::new(0));
-            let _ = self.socket.send(&ack.into_bytes()[..])?;
-
-            let conn = Connection::new(self.socket);
-            conn.get(f)?;
-            Ok(())
-        } else {
-            panic!("handler direction is wrong");
+    fn put(&self, wrq: &Packet<Wrq>) -> Result<()> {
+        let name = request_path(&wrq.body.0).map_err(|failure| self.conn.abort(failure))?;
+        authorize(
+            &*self.policy,
+            self.client,
+            &name,
+            &wrq.body.0,
+            Direction::Write,
+        )
+        .map_err(|failure| self.conn.abort(failure))?;
+
+        // Negotiate first, so that a file too large to accept is never
+        // created.
+        let receiver = machine::accept_write(wrq, self.transfer, &self.limits)
+            .map_err(|failure| self.conn.abort(failure))?;
+
+        let mut file = self
+            .storage
+            .open_write(&name, self.client, self.overwrite.get(&name))
+            .map_err(|e| self.conn.abort(Failure::local(e)))?;
+
+        let received = match wrq.body.0.mode {
+            Mode::NetAscii => self
+                .conn
+                .get(receiver, netascii::Decoder::new(&mut file))
+                .and_then(|decoder| Ok(decoder.finish().map(drop)?)),
+            _ => self.conn.get(receiver, &mut file).map(drop),
+        };
+
+        // Only a file that arrived in full is kept.
+        match received {
+            Ok(()) => Ok(file.commit()?),
+            Err(err) => {
+                file.abort();
+                Err(err)
+            }
+        }
+    }
+}
+
+/// What to do with files that already exist, by the names of the files.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OverwriteRules {
+    rules: Vec<(Glob, Overwrite)>,
+    default: Overwrite,
+}
+
+impl OverwriteRules {
+    /// What to do if the file `name` already exists.
+    pub(crate) fn get(&self, name: &Path) -> Overwrite {
+        self.rules
+            .iter()
+            .find(|(glob, _)| glob.matches(name))
+            .map_or(self.default, |&(_, overwrite)| overwrite)
+    }
+}
+
+/// The name of the file a request asks for, as a relative path made up of
+/// nothing but names.
+///
+/// Filenames with `..` and absolute paths are refused with an access
+/// violation, and filenames that make no path on this platform as not found.
+pub(crate) fn request_path(rq: &Rq) -> std::result::Result<PathBuf, Failure> {
+    let access_violation = || Failure::reply(crate::Error::protocol(Code::AccessViolation));
+
+    let filename = match rq.filename_os() {
+        Some(filename) => Path::new(filename),
+        None => return Err(Failure::reply(crate::Error::protocol(Code::FileNotFound))),
+    };
+
+    let mut path = PathBuf::new();
+    for component in filename.components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => (),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(access_violation())
+            }
         }
     }
+
+    if path.as_os_str().is_empty() {
+        return Err(access_violation());
+    }
+    Ok(path)
+}
+
+/// Asks `policy` whether `client` may read or write `name`, as `rq` asks.
+pub(crate) fn authorize(
+    policy: &dyn AccessPolicy,
+    client: SocketAddr,
+    name: &Path,
+    rq: &Rq,
+    direction: Direction,
+) -> std::result::Result<(), Failure> {
+    let access = Access {
+        client,
+        name,
+        mode: rq.mode,
+        direction,
+    };
+
+    policy
+        .check(&access)
+        .map_err(|code| Failure::reply(crate::Error::protocol(code)))
 }
@@ -0,0 +1,835 @@
+//! Where a server keeps the files it serves.
+//!
+//! A `Server` reads and writes files through a `Storage`, so that files can
+//! come from anywhere: the local file system, memory, or a store of your own.
+//! Four implementations are built in:
+//!
+//! - `FileSystem` serves a directory, which is what `Server::new` uses.
+//! - `Memory` keeps files in a map, for instance to serve test fixtures.
+//! - `Overlay` lays one storage over another that is only ever read from.
+//! - `Generated` makes up files as they are asked for.
+//!
+//! ```no_run
+//! use tftp::storage::{FileSystem, Memory, Overlay};
+//! use tftp::Server;
+//!
+//! # fn run() -> tftp::Result<()> {
+//! // Uploads go to memory, on top of the files of a directory.
+//! let storage = Overlay::new(Memory::new(), FileSystem::new("/srv/tftp"));
+//! let server = Server::with_storage("0.0.0.0:69", storage)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A place to keep files.
+///
+/// The names a storage is asked for are relative paths made up of nothing
+/// but names, since the server refuses `..`, absolute paths and the like
+/// before a storage is ever asked. `client` is the address of the client
+/// that asked for the file.
+///
+/// Errors are reported to the client with the code that matches their kind,
+/// such as "file not found" for `ErrorKind::NotFound`.
+///
+/// A storage may be called from several threads at once, and its methods
+/// may block.
+pub trait Storage: Send + Sync {
+    /// Opens the file `name` to be read.
+    fn open_read(&self, name: &Path, client: SocketAddr) -> io::Result<Box<dyn ReadFile>>;
+
+    /// Creates the file `name` to be written, doing what `overwrite` says
+    /// with a file that already exists.
+    fn open_write(
+        &self,
+        name: &Path,
+        client: SocketAddr,
+        overwrite: Overwrite,
+    ) -> io::Result<Box<dyn WriteFile>>;
+
+    /// Whether there is a file `name`, which is to be found out without side
+    /// effects.
+    ///
+    /// By default the file is opened to be read, which storages that do
+    /// anything more than that on opening a file should avoid.
+    fn exists(&self, name: &Path, client: SocketAddr) -> io::Result<bool> {
+        match self.open_read(name, client) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What to do when a client writes a file that already exists.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Overwrite {
+    /// Refuses the file with `ErrorKind::AlreadyExists`, which clients are
+    /// told as "file already exists".
+    #[default]
+    Reject,
+
+    /// Writes over the file once the new one has arrived in full.
+    Replace,
+
+    /// Keeps the file, and writes the new one under its name with the time
+    /// in UTC appended, such as `switch.cfg.20261016T021500Z`.
+    Timestamp,
+
+    /// Writes over the file once the new one has arrived in full, keeping up
+    /// to this many earlier versions, from `name.1`, the latest, to
+    /// `name.N`, the oldest.
+    Rotate(usize),
+}
+
+/// A file opened to be read.
+pub trait ReadFile: Read + Send {
+    /// The size of the file in bytes, if it is known up front, so that it
+    /// can be told to clients that ask for it (RFC 2349).
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A file opened to be written.
+///
+/// Once the transfer has finished, the file is either committed or aborted.
+pub trait WriteFile: Write + Send {
+    /// Keeps the file, all of which has been written.
+    fn commit(self: Box<Self>) -> io::Result<()>;
+
+    /// Throws away the file, whose transfer failed.
+    fn abort(self: Box<Self>);
+}
+
+impl ReadFile for File {
+    fn size(&self) -> Option<u64> {
+        self.metadata().ok().map(|m| m.len())
+    }
+}
+
+impl<T: AsRef<[u8]> + Send> ReadFile for Cursor<T> {
+    fn size(&self) -> Option<u64> {
+        Some(self.get_ref().as_ref().len() as u64)
+    }
+}
+
+/// The files of a directory.
+///
+/// # Confinement
+///
+/// Only files within the directory can be read or written. A name is
+/// resolved as far as it exists, symbolic links included, and refused with
+/// `ErrorKind::PermissionDenied` if that leads out of the directory. A file
+/// is written where its name resolves to, and is created anew, so a symbolic
+/// link that does not lead anywhere yet cannot be followed out either.
+///
+/// Names are resolved against the directory as it is when a file is opened,
+/// so the guarantee does not hold against anyone who can change the
+/// directory in the meantime, for example by swapping a directory for a
+/// symbolic link.
+#[derive(Clone, Debug)]
+pub struct FileSystem {
+    root: PathBuf,
+}
+
+impl FileSystem {
+    /// Serves the files of the directory `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    /// Resolves `name` to a path that lies within the directory.
+    fn resolve(&self, name: &Path) -> io::Result<PathBuf> {
+        let root = self.root.canonicalize()?;
+        let path = root.join(name);
+        let resolved = match path.canonicalize() {
+            Ok(resolved) => resolved,
+            // A file that is yet to be written only has a parent to resolve.
+            Err(e) if e.kind() == ErrorKind::NotFound => match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => parent.canonicalize()?.join(name),
+                _ => return Err(e),
+            },
+            Err(e) => return Err(e),
+        };
+
+        if resolved.starts_with(&root) && resolved != root {
+            Ok(resolved)
+        } else {
+            Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "path leads out of the served directory",
+            ))
+        }
+    }
+}
+
+impl Storage for FileSystem {
+    fn open_read(&self, name: &Path, _client: SocketAddr) -> io::Result<Box<dyn ReadFile>> {
+        let file = File::open(self.resolve(name)?)?;
+        Ok(Box::new(file))
+    }
+
+    fn open_write(
+        &self,
+        name: &Path,
+        _client: SocketAddr,
+        overwrite: Overwrite,
+    ) -> io::Result<Box<dyn WriteFile>> {
+        let path = self.resolve(name)?;
+        let create = |path: PathBuf| -> io::Result<Box<dyn WriteFile>> {
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            Ok(Box::new(NewFile {
+                file,
+                path,
+                replace: None,
+            }))
+        };
+
+        match overwrite {
+            Overwrite::Reject => create(path),
+            Overwrite::Timestamp => {
+                let mut result = create(path.clone());
+                for path in timestamped(&path) {
+                    match result {
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => result = create(path),
+                        result => return result,
+                    }
+                }
+                unreachable!("there are endless timestamped names")
+            }
+            Overwrite::Replace | Overwrite::Rotate(_) => {
+                // Written next to the file, so that it can be moved over the
+                // file once it has arrived in full.
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let (file, temp) = loop {
+                    let temp_name = format!(".{}.{:08x}.part", file_name, rand::random::<u32>());
+                    let temp = path.with_file_name(temp_name);
+                    match OpenOptions::new().write(true).create_new(true).open(&temp) {
+                        Ok(file) => break (file, temp),
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                        Err(e) => return Err(e),
+                    }
+                };
+
+                let keep = match overwrite {
+                    Overwrite::Rotate(keep) => keep,
+                    _ => 0,
+                };
+                Ok(Box::new(NewFile {
+                    file,
+                    path: temp,
+                    replace: Some((path, keep)),
+                }))
+            }
+        }
+    }
+
+    fn exists(&self, name: &Path, _client: SocketAddr) -> io::Result<bool> {
+        match self.resolve(name) {
+            Ok(path) => path.try_exists(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A file that is removed again unless it is committed.
+struct NewFile {
+    file: File,
+    path: PathBuf,
+
+    /* the file to move this one over, and how many versions of it to keep */
+    replace: Option<(PathBuf, usize)>,
+}
+
+impl Write for NewFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl WriteFile for NewFile {
+    fn commit(self: Box<Self>) -> io::Result<()> {
+        self.file.sync_all()?;
+
+        if let Some((ref target, keep)) = self.replace {
+            let result = rotate(target, keep, |from, to| match fs::rename(from, to) {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            })
+            .and_then(|()| fs::rename(&self.path, target));
+
+            if result.is_err() {
+                let _ = fs::remove_file(&self.path);
+            }
+            return result;
+        }
+        Ok(())
+    }
+
+    fn abort(self: Box<Self>) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Files kept in memory.
+///
+/// Clones share the same files, so a clone can be kept to look at what
+/// clients wrote to a server.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    files: Arc<Mutex<HashMap<PathBuf, Arc<[u8]>>>>,
+}
+
+impl Memory {
+    /// Creates a storage without any files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any file of the same name.
+    pub fn insert<P: AsRef<Path>, T: Into<Vec<u8>>>(&self, name: P, contents: T) {
+        let contents: Vec<u8> = contents.into();
+        let mut files = self.files.lock().unwrap();
+        files.insert(name.as_ref().to_owned(), contents.into());
+    }
+
+    /// The contents of a file, if there is one of that name.
+    pub fn get<P: AsRef<Path>>(&self, name: P) -> Option<Vec<u8>> {
+        let files = self.files.lock().unwrap();
+        files.get(name.as_ref()).map(|contents| contents.to_vec())
+    }
+
+    /// Removes a file, returning its contents if there was one.
+    pub fn remove<P: AsRef<Path>>(&self, name: P) -> Option<Vec<u8>> {
+        let mut files = self.files.lock().unwrap();
+        files
+            .remove(name.as_ref())
+            .map(|contents| contents.to_vec())
+    }
+}
+
+impl Storage for Memory {
+    fn open_read(&self, name: &Path, _client: SocketAddr) -> io::Result<Box<dyn ReadFile>> {
+        let files = self.files.lock().unwrap();
+        match files.get(name) {
+            Some(contents) => Ok(Box::new(Cursor::new(Arc::clone(contents)))),
+            None => Err(ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn open_write(
+        &self,
+        name: &Path,
+        _client: SocketAddr,
+        overwrite: Overwrite,
+    ) -> io::Result<Box<dyn WriteFile>> {
+        let files = self.files.lock().unwrap();
+        let name = match overwrite {
+            Overwrite::Reject if files.contains_key(name) => {
+                return Err(ErrorKind::AlreadyExists.into())
+            }
+            Overwrite::Timestamp if files.contains_key(name) => timestamped(name)
+                .find(|name| !files.contains_key(name))
+                .expect("there are endless timestamped names"),
+            _ => name.to_owned(),
+        };
+
+        Ok(Box::new(MemoryFile {
+            files: Arc::clone(&self.files),
+            name,
+            overwrite,
+            contents: Vec::new(),
+        }))
+    }
+
+    fn exists(&self, name: &Path, _client: SocketAddr) -> io::Result<bool> {
+        Ok(self.files.lock().unwrap().contains_key(name))
+    }
+}
+
+/// A file that is added to a `Memory` once it is committed.
+struct MemoryFile {
+    files: Arc<Mutex<HashMap<PathBuf, Arc<[u8]>>>>,
+    name: PathBuf,
+    overwrite: Overwrite,
+    contents: Vec<u8>,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.contents.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteFile for MemoryFile {
+    fn commit(self: Box<Self>) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        match self.overwrite {
+            // Another client may have written a file of the same name
+            // meanwhile.
+            Overwrite::Reject | Overwrite::Timestamp if files.contains_key(&self.name) => {
+                return Err(ErrorKind::AlreadyExists.into());
+            }
+            Overwrite::Rotate(keep) => rotate(&self.name, keep, |from, to| {
+                if let Some(contents) = files.remove(from) {
+                    files.insert(to.to_owned(), contents);
+                }
+                Ok(())
+            })?,
+            _ => (),
+        }
+        files.insert(self.name, self.contents.into());
+        Ok(())
+    }
+
+    fn abort(self: Box<Self>) {}
+}
+
+/// A storage laid over another that is only ever read from.
+///
+/// Files are read from the upper storage, or from the lower one if the upper
+/// one has no file of that name. Files are only written to the upper
+/// storage, and never over a file of the lower one.
+#[derive(Clone, Debug)]
+pub struct Overlay<U, L> {
+    upper: U,
+    lower: L,
+}
+
+impl<U: Storage, L: Storage> Overlay<U, L> {
+    /// Lays `upper` over `lower`.
+    pub fn new(upper: U, lower: L) -> Self {
+        Self { upper, lower }
+    }
+}
+
+impl<U: Storage, L: Storage> Storage for Overlay<U, L> {
+    fn open_read(&self, name: &Path, client: SocketAddr) -> io::Result<Box<dyn ReadFile>> {
+        match self.upper.open_read(name, client) {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.lower.open_read(name, client),
+            result => result,
+        }
+    }
+
+    fn open_write(
+        &self,
+        name: &Path,
+        client: SocketAddr,
+        overwrite: Overwrite,
+    ) -> io::Result<Box<dyn WriteFile>> {
+        if self.lower.exists(name, client)? {
+            return Err(ErrorKind::AlreadyExists.into());
+        }
+        self.upper.open_write(name, client, overwrite)
+    }
+
+    fn exists(&self, name: &Path, client: SocketAddr) -> io::Result<bool> {
+        Ok(self.upper.exists(name, client)? || self.lower.exists(name, client)?)
+    }
+}
+
+/// Files that are made up as they are asked for, laid over a storage for
+/// every other file.
+///
+/// `generate` is called with the name of every file that is to be read and
+/// the client that asked for it. It returns `None` for the names it does not
+/// make up, which are then read from the storage below, and the file to send
+/// for every other name. The file is sent as it is read, so it can be
+/// rendered on the fly rather than ahead of time. Files are only ever
+/// written to the storage below.
+///
+/// Only the storage below is asked whether a file exists, since finding out
+/// whether a file would be made up means making it up. To keep clients from
+/// writing files of the names that are made up, lay a `Generated` over an
+/// `Overlay` rather than under one.
+///
+/// ```no_run
+/// use std::io::Cursor;
+/// use std::path::Path;
+///
+/// use tftp::storage::{FileSystem, Generated, ReadFile};
+/// use tftp::Server;
+///
+/// # fn run() -> tftp::Result<()> {
+/// let storage = Generated::new(
+///     |name: &Path, client| {
+///         let host = name.strip_prefix("pxelinux.cfg").ok()?;
+///         let config = format!("# {} from {}\nDEFAULT linux\n", host.display(), client.ip());
+///         Some(Ok(Box::new(Cursor::new(config)) as Box<dyn ReadFile>))
+///     },
+///     FileSystem::new("/srv/tftp"),
+/// );
+/// let server = Server::with_storage("0.0.0.0:69", storage)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Generated<F, S> {
+    generate: F,
+    below: S,
+}
+
+impl<F, S> Generated<F, S>
+where
+    F: Fn(&Path, SocketAddr) -> Option<io::Result<Box<dyn ReadFile>>> + Send + Sync,
+    S: Storage,
+{
+    /// Lays the files that `generate` makes up over `below`.
+    pub fn new(generate: F, below: S) -> Self {
+        Self { generate, below }
+    }
+}
+
+impl<F, S> Storage for Generated<F, S>
+where
+    F: Fn(&Path, SocketAddr) -> Option<io::Result<Box<dyn ReadFile>>> + Send + Sync,
+    S: Storage,
+{
+    fn open_read(&self, name: &Path, client: SocketAddr) -> io::Result<Box<dyn ReadFile>> {
+        match (self.generate)(name, client) {
+            Some(result) => result,
+            None => self.below.open_read(name, client),
+        }
+    }
+
+    fn open_write(
+        &self,
+        name: &Path,
+        client: SocketAddr,
+        overwrite: Overwrite,
+    ) -> io::Result<Box<dyn WriteFile>> {
+        self.below.open_write(name, client, overwrite)
+    }
+
+    fn exists(&self, name: &Path, client: SocketAddr) -> io::Result<bool> {
+        self.below.exists(name, client)
+    }
+}
+
+/// `name` with `suffix` appended after a dot.
+fn with_suffix(name: &Path, suffix: &str) -> PathBuf {
+    let mut name = name.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The names to write a file of the name `name` to instead, for
+/// `Overwrite::Timestamp`.
+fn timestamped(name: &Path) -> impl Iterator<Item = PathBuf> {
+    let stamped = with_suffix(name, &timestamp(SystemTime::now()));
+    let again = (1..).map({
+        let stamped = stamped.clone();
+        move |n: u32| with_suffix(&stamped, &n.to_string())
+    });
+    std::iter::once(stamped).chain(again)
+}
+
+/// Moves `name.1` to `name.2` and so on, and then `name` to `name.1`, to
+/// make way for a new version of `name` while keeping `keep` earlier ones.
+/// `rename` moves a file over another, or does nothing if there is none.
+fn rotate<F>(name: &Path, keep: usize, mut rename: F) -> io::Result<()>
+where
+    F: FnMut(&Path, &Path) -> io::Result<()>,
+{
+    for n in (1..keep).rev() {
+        let from = with_suffix(name, &n.to_string());
+        rename(&from, &with_suffix(name, &(n + 1).to_string()))?;
+    }
+    if keep > 0 {
+        rename(name, &with_suffix(name, "1"))?;
+    }
+    Ok(())
+}
+
+/// `time` in UTC, in the basic format of ISO 8601, such as
+/// `20261016T021500Z`.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // From days to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn client() -> SocketAddr {
+        "127.0.0.1:1234".parse().unwrap()
+    }
+
+    fn read(storage: &dyn Storage, name: &str) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        storage
+            .open_read(Path::new(name), client())?
+            .read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn write(storage: &dyn Storage, name: &str, contents: &[u8]) -> io::Result<()> {
+        write_with(storage, name, contents, Overwrite::Reject)
+    }
+
+    fn write_with(
+        storage: &dyn Storage,
+        name: &str,
+        contents: &[u8],
+        overwrite: Overwrite,
+    ) -> io::Result<()> {
+        let mut file = storage.open_write(Path::new(name), client(), overwrite)?;
+        file.write_all(contents)?;
+        file.commit()
+    }
+
+    #[test]
+    fn test_memory() {
+        let storage = Memory::new();
+        storage.insert("fixture.bin", &b"fixture"[..]);
+
+        let file = storage
+            .open_read(Path::new("fixture.bin"), client())
+            .unwrap();
+        assert_eq!(file.size(), Some(7));
+        assert_eq!(read(&storage, "fixture.bin").unwrap(), b"fixture");
+        let err = read(&storage, "missing.bin").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        write(&storage, "upload.bin", b"upload").unwrap();
+        assert_eq!(storage.get("upload.bin").unwrap(), b"upload");
+        let err = write(&storage, "upload.bin", b"again").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        // Nothing is kept of an aborted file.
+        let mut file = storage
+            .open_write(Path::new("aborted.bin"), client(), Overwrite::Reject)
+            .unwrap();
+        file.write_all(b"partial").unwrap();
+        file.abort();
+        assert_eq!(storage.get("aborted.bin"), None);
+    }
+
+    #[test]
+    fn test_file_system() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("outside.txt"), "secret").unwrap();
+        fs::create_dir(dir.path().join("serve")).unwrap();
+        let storage = FileSystem::new(dir.path().join("serve"));
+
+        write(&storage, "upload.txt", b"upload").unwrap();
+        assert_eq!(read(&storage, "upload.txt").unwrap(), b"upload");
+        let err = write(&storage, "upload.txt", b"again").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let mut file = storage
+            .open_write(Path::new("aborted.txt"), client(), Overwrite::Reject)
+            .unwrap();
+        file.write_all(b"partial").unwrap();
+        file.abort();
+        assert!(!dir.path().join("serve/aborted.txt").exists());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("..", dir.path().join("serve/up")).unwrap();
+            let err = read(&storage, "up/outside.txt").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+            let err = write(&storage, "up/planted.txt", b"planted").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn test_overlay() {
+        let upper = Memory::new();
+        let lower = Memory::new();
+        lower.insert("firmware.bin", &b"lower"[..]);
+        lower.insert("shadowed.bin", &b"lower"[..]);
+        upper.insert("shadowed.bin", &b"upper"[..]);
+        let storage = Overlay::new(upper.clone(), lower.clone());
+
+        assert_eq!(read(&storage, "firmware.bin").unwrap(), b"lower");
+        assert_eq!(read(&storage, "shadowed.bin").unwrap(), b"upper");
+
+        write(&storage, "upload.bin", b"upload").unwrap();
+        assert_eq!(upper.get("upload.bin").unwrap(), b"upload");
+        assert_eq!(lower.get("upload.bin"), None);
+
+        let err = write(&storage, "firmware.bin", b"over").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(lower.get("firmware.bin").unwrap(), b"lower");
+    }
+
+    #[test]
+    fn test_generated() {
+        let below = Memory::new();
+        below.insert("static.cfg", &b"static"[..]);
+        let storage = Generated::new(
+            |name: &Path, client: SocketAddr| {
+                let host = name.strip_prefix("hosts").ok()?;
+                if host.as_os_str() == "missing" {
+                    return Some(Err(ErrorKind::NotFound.into()));
+                }
+                let contents = format!("{} {}", host.display(), client.port());
+                Some(Ok(Box::new(Cursor::new(contents)) as Box<dyn ReadFile>))
+            },
+            below.clone(),
+        );
+
+        assert_eq!(read(&storage, "hosts/alpha").unwrap(), b"alpha 1234");
+        assert_eq!(read(&storage, "static.cfg").unwrap(), b"static");
+        let err = read(&storage, "hosts/missing").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        write(&storage, "upload.bin", b"upload").unwrap();
+        assert_eq!(below.get("upload.bin").unwrap(), b"upload");
+    }
+
+    #[test]
+    fn test_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("present.txt"), "present").unwrap();
+        let storage = FileSystem::new(dir.path());
+        assert!(storage.exists(Path::new("present.txt"), client()).unwrap());
+        assert!(!storage.exists(Path::new("absent.txt"), client()).unwrap());
+        assert!(!storage
+            .exists(Path::new("absent/absent.txt"), client())
+            .unwrap());
+
+        // Writing over the overlay only asks the storage below whether the
+        // file exists, and makes nothing up.
+        let below = Memory::new();
+        below.insert("firmware.bin", &b"lower"[..]);
+        let generated = AtomicUsize::new(0);
+        let lower = Generated::new(
+            |_: &Path, _| {
+                generated.fetch_add(1, Ordering::SeqCst);
+                None
+            },
+            below,
+        );
+        let storage = Overlay::new(Memory::new(), lower);
+
+        write(&storage, "upload.bin", b"upload").unwrap();
+        let err = write(&storage, "firmware.bin", b"over").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(storage.exists(Path::new("upload.bin"), client()).unwrap());
+        assert_eq!(generated.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let at = |secs| timestamp(UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "19700101T000000Z");
+        assert_eq!(at(951_782_400), "20000229T000000Z");
+        assert_eq!(at(1_000_000_000), "20010909T014640Z");
+        assert_eq!(at(1_709_208_000), "20240229T120000Z");
+        assert_eq!(at(4_102_444_799), "20991231T235959Z");
+    }
+
+    fn test_overwrite(storage: &dyn Storage) {
+        write(storage, "switch.cfg", b"monday").unwrap();
+        let err = write(storage, "switch.cfg", b"tuesday").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        write_with(storage, "switch.cfg", b"tuesday", Overwrite::Replace).unwrap();
+        assert_eq!(read(storage, "switch.cfg").unwrap(), b"tuesday");
+        assert_eq!(
+            read(storage, "switch.cfg.1").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+
+        for day in ["wednesday", "thursday", "friday"] {
+            write_with(storage, "switch.cfg", day.as_bytes(), Overwrite::Rotate(2)).unwrap();
+        }
+        assert_eq!(read(storage, "switch.cfg").unwrap(), b"friday");
+        assert_eq!(read(storage, "switch.cfg.1").unwrap(), b"thursday");
+        assert_eq!(read(storage, "switch.cfg.2").unwrap(), b"wednesday");
+        assert!(read(storage, "switch.cfg.3").is_err());
+
+        // The file is only written over once the new one is committed.
+        let mut file = storage
+            .open_write(Path::new("switch.cfg"), client(), Overwrite::Rotate(2))
+            .unwrap();
+        file.write_all(b"partial").unwrap();
+        file.abort();
+        assert_eq!(read(storage, "switch.cfg").unwrap(), b"friday");
+
+        let stamped = timestamped(Path::new("switch.cfg")).next().unwrap();
+        write_with(storage, "switch.cfg", b"saturday", Overwrite::Timestamp).unwrap();
+        assert_eq!(read(storage, "switch.cfg").unwrap(), b"friday");
+        let stamped = stamped.to_str().unwrap();
+        assert!(stamped.starts_with("switch.cfg.") && stamped.ends_with('Z'));
+        let again = format!("{}.1", stamped);
+        write_with(storage, "switch.cfg", b"sunday", Overwrite::Timestamp).unwrap();
+
+        // Both timestamped versions are kept, unless the clock ticked over
+        // in between.
+        if let (Ok(saturday), Ok(sunday)) = (read(storage, stamped), read(storage, &again)) {
+            assert_eq!(saturday, b"saturday");
+            assert_eq!(sunday, b"sunday");
+        }
+    }
+
+    #[test]
+    fn test_overwrite_memory() {
+        test_overwrite(&Memory::new());
+    }
+
+    #[test]
+    fn test_overwrite_file_system() {
+        let dir = tempfile::tempdir().unwrap();
+        test_overwrite(&FileSystem::new(dir.path()));
+
+        // Nothing is left behind of the aborted file.
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(names
+            .iter()
+            .all(|name| !name.to_string_lossy().ends_with(".part")));
+    }
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use tftp::access::{Access, Direction, Glob, Paths, Subnets, Writes};
+use tftp::packet::{Code, Mode};
+use tftp::storage::Memory;
+use tftp::Server;
+
+use common::{assert_refused, spawn};
+
+#[test]
+fn test_uploads_limited_to_subnet() {
+    let memory = Memory::new();
+    memory.insert("firmware.bin", &b"firmware"[..]);
+    let backups = Subnets::allow(vec!["10.20.0.0/24".parse().unwrap()]);
+
+    let server = Server::with_storage("127.0.0.1:0", memory.clone())
+        .unwrap()
+        .access_policy(Writes(backups.clone()));
+    let (client, server_thread) = spawn(server);
+    let actual = client.get("firmware.bin", Mode::Octet, Vec::new()).unwrap();
+    assert_eq!(actual, b"firmware");
+    server_thread.join().unwrap().unwrap();
+
+    let server = Server::with_storage("127.0.0.1:0", memory.clone())
+        .unwrap()
+        .access_policy(Writes(backups));
+    let (client, server_thread) = spawn(server);
+    assert_refused(
+        client.put("switch.cfg", Mode::Octet, &b"config"[..]),
+        Code::AccessViolation,
+    );
+    server_thread.join().unwrap().unwrap_err();
+    assert!(memory.get("switch.cfg").is_none());
+}
+
+#[test]
+fn test_paths_and_closures() {
+    let memory = Memory::new();
+    memory.insert("secrets/key", &b"key"[..]);
+    let policy = (
+        Paths::deny(vec![Glob::new("secrets/**")]),
+        |access: &Access<'_>| match (access.direction, access.mode) {
+            (Direction::Write, Mode::Mail) => Err(Code::NoSuchUser),
+            _ => Ok(()),
+        },
+    );
+
+    let server = Server::with_storage("127.0.0.1:0", memory.clone())
+        .unwrap()
+        .access_policy(policy);
+    let (client, server_thread) = spawn(server);
+    assert_refused(
+        client.get("secrets/key", Mode::Octet, Vec::new()),
+        Code::AccessViolation,
+    );
+    server_thread.join().unwrap().unwrap_err();
+}
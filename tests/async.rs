@@ -0,0 +1,194 @@
+#![cfg(feature = "async")]
+
+use tftp::client;
+use tftp::packet::{AnyPacket, Block, Code, Mode, Packet};
+use tftp::storage::Memory;
+use tftp::{AsyncClient, AsyncServer, Server};
+
+fn exemplar() -> &'static [u8] {
+    include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/artifacts/alice-in-wonderland.txt"
+    ))
+}
+
+fn server<P: AsRef<std::path::Path>>(serve_dir: P) -> AsyncServer {
+    Server::new("127.0.0.1:0", serve_dir)
+        .unwrap()
+        .into_async()
+        .unwrap()
+}
+
+fn client(server: &AsyncServer) -> AsyncClient {
+    client::Builder::new()
+        .unwrap()
+        .connect_to(server.local_addr().unwrap())
+        .unwrap()
+        .build_async()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_get() {
+    let server = server(concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts"));
+    let client = client(&server);
+
+    let server_task = tokio::spawn(async move {
+        let handler = server.serve().await.unwrap();
+        handler.handle().await.unwrap();
+    });
+
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::NetAscii, Vec::new())
+        .await
+        .unwrap();
+    assert_eq!(&actual[..], exemplar());
+
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_get_with_size() {
+    let server = server(concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts"));
+    let client = client(&server);
+
+    let server_task = tokio::spawn(async move {
+        let handler = server.serve().await.unwrap();
+        handler.handle().await.unwrap();
+    });
+
+    let mut announced = None;
+    let actual = client
+        .get_with_size(
+            "alice-in-wonderland.txt",
+            Mode::Octet,
+            Vec::new(),
+            |size, _: &mut Vec<u8>| announced = size,
+        )
+        .await
+        .unwrap();
+    assert_eq!(announced, Some(exemplar().len() as u64));
+    assert_eq!(&actual[..], exemplar());
+
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_put() {
+    let serve_dir = tempfile::tempdir().unwrap();
+    let server = server(serve_dir.path());
+    let client = client(&server);
+
+    let server_task = tokio::spawn(async move {
+        let handler = server.serve().await.unwrap();
+        handler.handle().await.unwrap();
+    });
+
+    client
+        .put("alice-in-wonderland.txt", Mode::NetAscii, exemplar())
+        .await
+        .unwrap();
+    server_task.await.unwrap();
+
+    let actual = std::fs::read(serve_dir.path().join("alice-in-wonderland.txt")).unwrap();
+    assert_eq!(&actual[..], exemplar());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_concurrent_transfers() {
+    const TRANSFERS: usize = 32;
+
+    let server = server(concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts"));
+    let clients: Vec<_> = (0..TRANSFERS).map(|_| client(&server)).collect();
+
+    // A single thread serves every transfer at once.
+    let server_task = tokio::spawn(async move {
+        let mut handlers = Vec::new();
+        for _ in 0..TRANSFERS {
+            let handler = server.serve().await.unwrap();
+            handlers.push(tokio::spawn(handler.handle()));
+        }
+        for handler in handlers {
+            handler.await.unwrap().unwrap();
+        }
+    });
+
+    let transfers: Vec<_> = clients
+        .into_iter()
+        .map(|client| tokio::spawn(client.get("alice-in-wonderland.txt", Mode::Octet, Vec::new())))
+        .collect();
+
+    for transfer in transfers {
+        let actual = transfer.await.unwrap().unwrap();
+        assert_eq!(&actual[..], exemplar());
+    }
+
+    server_task.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_put_to_memory() {
+    let memory = Memory::new();
+    let server = Server::with_storage("127.0.0.1:0", memory.clone())
+        .unwrap()
+        .into_async()
+        .unwrap();
+    let client = client(&server);
+
+    let server_task = tokio::spawn(async move {
+        let handler = server.serve().await.unwrap();
+        handler.handle().await.unwrap();
+    });
+
+    client
+        .put("alice-in-wonderland.txt", Mode::NetAscii, exemplar())
+        .await
+        .unwrap();
+    server_task.await.unwrap();
+
+    assert_eq!(memory.get("alice-in-wonderland.txt").unwrap(), exemplar());
+}
+
+#[tokio::test]
+async fn test_get_refuses_strangers() {
+    let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    // Any address of 127.0.0.0/8 is on the loopback interface on Linux,
+    // but elsewhere there may be no other address to answer from.
+    let stranger = match tokio::net::UdpSocket::bind("127.0.0.2:0").await {
+        Ok(stranger) => stranger,
+        Err(_) => return,
+    };
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server.local_addr().unwrap())
+        .unwrap()
+        .build_async()
+        .unwrap();
+    let client_task =
+        tokio::spawn(async move { client.get("file", Mode::Octet, Vec::new()).await });
+
+    let mut buf = vec![0; 1024];
+    let (_, client_addr) = server.recv_from(&mut buf).await.unwrap();
+
+    // A stranger answers before the server does.
+    let forged = AnyPacket::from(Packet::data(Block::new(1), b"forged")).into_bytes();
+    stranger.send_to(&forged, client_addr).await.unwrap();
+    let nbytes = stranger.recv(&mut buf).await.unwrap();
+    match AnyPacket::from_bytes(&buf[..nbytes]).unwrap() {
+        AnyPacket::Error(error) => assert_eq!(error.body.code, Code::UnknownTid),
+        other => panic!("expected an error, got {:?}", other),
+    }
+
+    let transfer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    transfer.connect(client_addr).await.unwrap();
+    let genuine = AnyPacket::from(Packet::data(Block::new(1), b"genuine")).into_bytes();
+    transfer.send(&genuine).await.unwrap();
+    let nbytes = transfer.recv(&mut buf).await.unwrap();
+    assert_eq!(
+        AnyPacket::from_bytes(&buf[..nbytes]).unwrap(),
+        AnyPacket::from(Packet::ack(Block::new(1)))
+    );
+
+    assert_eq!(client_task.await.unwrap().unwrap(), b"genuine");
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "codec")]
+
+use std::thread;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::UdpSocket;
+use tokio_util::udp::UdpFramed;
+
+use tftp::codec::TftpCodec;
+use tftp::packet::{AnyPacket, Block, Code, Mode, Packet};
+use tftp::Server;
+
+#[tokio::test]
+async fn test_framed_read_request() {
+    let serve_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts");
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir).unwrap();
+    let server_addr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap_err();
+    });
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut framed = UdpFramed::new(socket, TftpCodec);
+
+    let rrq = Packet::rrq("alice-in-wonderland.txt", Mode::Octet);
+    framed.send((rrq.into(), server_addr)).await.unwrap();
+
+    let (packet, transfer_addr) = framed.next().await.unwrap().unwrap();
+    match packet {
+        AnyPacket::Data(data) => assert_eq!(data.body.block, Block::new(1)),
+        packet => panic!("expected the first block, got {:?}", packet),
+    }
+
+    let error = Packet::error(Code::NotDefined, "that will do");
+    framed.send((error.into(), transfer_addr)).await.unwrap();
+
+    server_thread.join().unwrap();
+}
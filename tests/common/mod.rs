@@ -0,0 +1,53 @@
+//! Helpers shared by the integration tests.
+
+// Every test binary compiles this module, but not every one uses all of it.
+#![allow(dead_code)]
+
+use std::fmt::Debug;
+use std::thread;
+
+use tftp::client::{self, Client};
+use tftp::packet::Code;
+use tftp::{ConnectTo, Error, Result, Server};
+
+/// The contents of `artifacts/alice-in-wonderland.txt`.
+pub const EXEMPLAR: &[u8] = include_bytes!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/artifacts/alice-in-wonderland.txt"
+));
+
+/// A server for the `artifacts` directory, on a port of its own.
+pub fn serve_artifacts() -> Server {
+    let serve_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts");
+    Server::new("127.0.0.1:0", serve_dir).unwrap()
+}
+
+/// Serves a single request on a thread of its own, and returns a client
+/// that talks to the server.
+pub fn spawn(server: Server) -> (Client, thread::JoinHandle<Result<()>>) {
+    spawn_with(server, |builder| builder)
+}
+
+/// Like `spawn`, but lets `configure` set up the client, such as the
+/// options it requests.
+pub fn spawn_with<F>(server: Server, configure: F) -> (Client, thread::JoinHandle<Result<()>>)
+where
+    F: FnOnce(client::Builder<ConnectTo>) -> client::Builder<ConnectTo>,
+{
+    let builder = client::Builder::new()
+        .unwrap()
+        .connect_to(server.local_addr().unwrap())
+        .unwrap();
+    let client = configure(builder).build();
+
+    let server_thread = thread::spawn(move || server.serve()?.handle());
+    (client, server_thread)
+}
+
+/// Checks that the server refused a request with `expected`.
+pub fn assert_refused<T: Debug>(result: Result<T>, expected: Code) {
+    match result {
+        Err(Error::Remote { code, .. }) => assert_eq!(code, expected),
+        other => panic!("expected the request to be refused, got {:?}", other),
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+
+use tftp::client;
+use tftp::packet::{Code, Mode};
+use tftp::{Client, Error, Server};
+
+/// Lays out a directory to serve next to a file that must stay out of reach.
+fn layout() -> tempfile::TempDir {
+    let root = tempfile::tempdir().unwrap();
+    fs::write(root.path().join("outside.txt"), "secret").unwrap();
+
+    let serve_dir = root.path().join("serve");
+    fs::create_dir_all(serve_dir.join("sub")).unwrap();
+    fs::write(serve_dir.join("inside.txt"), "public").unwrap();
+    fs::write(serve_dir.join("sub/nested.txt"), "nested").unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::symlink;
+        symlink("../outside.txt", serve_dir.join("escape")).unwrap();
+        symlink("..", serve_dir.join("escape_dir")).unwrap();
+        symlink("inside.txt", serve_dir.join("inner_link")).unwrap();
+    }
+
+    root
+}
+
+/// Starts a server on `serve_dir` that handles a single request.
+fn serve_once(serve_dir: &Path) -> (Client, JoinHandle<tftp::Result<()>>) {
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir).unwrap();
+    let server_thread = thread::spawn(move || server.serve()?.handle());
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(format!("127.0.0.1:{}", port))
+        .unwrap()
+        .build();
+
+    (client, server_thread)
+}
+
+fn get(serve_dir: &Path, filename: &str) -> tftp::Result<Vec<u8>> {
+    let (client, server_thread) = serve_once(serve_dir);
+    let result = client.get(filename, Mode::Octet, Vec::new());
+    let _ = server_thread.join().unwrap();
+    result
+}
+
+fn put(serve_dir: &Path, filename: &str) -> tftp::Result<()> {
+    let (client, server_thread) = serve_once(serve_dir);
+    let result = client.put(filename, Mode::Octet, &b"planted"[..]);
+    let _ = server_thread.join().unwrap();
+    result
+}
+
+fn assert_access_violation<T: std::fmt::Debug>(result: tftp::Result<T>) {
+    match result {
+        Err(Error::Remote { code, .. }) => assert_eq!(code, Code::AccessViolation),
+        result => panic!("expected an access violation, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_get_within() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    assert_eq!(get(&serve_dir, "inside.txt").unwrap(), b"public");
+    assert_eq!(get(&serve_dir, "./inside.txt").unwrap(), b"public");
+    assert_eq!(get(&serve_dir, "sub/nested.txt").unwrap(), b"nested");
+
+    #[cfg(unix)]
+    assert_eq!(get(&serve_dir, "inner_link").unwrap(), b"public");
+}
+
+#[test]
+fn test_get_parent_dir() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    assert_access_violation(get(&serve_dir, "../outside.txt"));
+    assert_access_violation(get(&serve_dir, "sub/../../outside.txt"));
+    assert_access_violation(get(&serve_dir, "sub/../inside.txt"));
+}
+
+#[test]
+fn test_get_absolute() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    let outside = root.path().join("outside.txt");
+    assert_access_violation(get(&serve_dir, outside.to_str().unwrap()));
+    assert_access_violation(get(&serve_dir, "/etc/passwd"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_get_symlink_escape() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    assert_access_violation(get(&serve_dir, "escape"));
+    assert_access_violation(get(&serve_dir, "escape_dir/outside.txt"));
+}
+
+#[test]
+fn test_get_missing() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    match get(&serve_dir, "missing.txt") {
+        Err(Error::Remote { code, .. }) => assert_eq!(code, Code::FileNotFound),
+        result => panic!("expected file not found, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_put_within() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    put(&serve_dir, "sub/new.txt").unwrap();
+    assert_eq!(fs::read(serve_dir.join("sub/new.txt")).unwrap(), b"planted");
+}
+
+#[test]
+fn test_put_escape() {
+    let root = layout();
+    let serve_dir = root.path().join("serve");
+
+    assert_access_violation(put(&serve_dir, "../planted.txt"));
+    let planted = root.path().join("planted.txt");
+    assert_access_violation(put(&serve_dir, planted.to_str().unwrap()));
+
+    #[cfg(unix)]
+    assert_access_violation(put(&serve_dir, "escape_dir/planted.txt"));
+
+    assert!(!planted.exists());
+}
@@ -1,8 +1,14 @@
+mod common;
+
+use std::net::UdpSocket;
+use std::time::Duration;
 use std::{io, thread};
 
 use tftp::client;
-use tftp::packet::Mode;
-use tftp::Server;
+use tftp::packet::{AnyPacket, Block, Code, Mode, Options, Packet, Rollover, Strictness};
+use tftp::{Error, Server};
+
+use common::{serve_artifacts, spawn, spawn_with, EXEMPLAR};
 
 #[test]
 fn test_get() {
@@ -38,6 +44,7 @@ fn test_get() {
 #[derive(Debug)]
 struct ErroneousWriter;
 
+#[allow(clippy::io_other_error, clippy::useless_format)]
 impl io::Write for ErroneousWriter {
     fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
         Err(io::Error::new(io::ErrorKind::Other, format!("Fake error")))
@@ -74,9 +81,217 @@ fn test_get_sends_error() {
     let error = client
         .get("alice-in-wonderland.txt", Mode::NetAscii, ErroneousWriter)
         .unwrap_err();
-    assert_eq!(error.kind(), io::ErrorKind::Other);
-    assert_eq!(format!("{}", error.into_inner().unwrap()), "Fake error");
+    match error {
+        Error::Io(error) => {
+            assert_eq!(error.kind(), io::ErrorKind::Other);
+            assert_eq!(format!("{}", error.into_inner().unwrap()), "Fake error");
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
 
     // When receiving an error packet (due to the broken writer), the server should error out as well
+    match server_thread.join().unwrap().unwrap_err() {
+        Error::Remote { code, message } => {
+            assert_eq!(code, Code::NotDefined);
+            assert_eq!(message, "Fake error");
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+}
+
+#[test]
+fn test_get_ignores_unknown_options() {
+    let (client, server_thread) = spawn_with(serve_artifacts(), |builder| {
+        builder.option("x-vendor-option", "1")
+    });
+
+    let actual = Vec::with_capacity(EXEMPLAR.len());
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::NetAscii, actual)
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_get_with_block_size() {
+    // The server honors block sizes within its limit...
+    let server = serve_artifacts().max_block_size(8192);
+    let (client, server_thread) = spawn_with(server, |builder| builder.block_size(1428));
+
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::Octet, Vec::new())
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+    server_thread.join().unwrap().unwrap();
+
+    // ...and clamps those that exceed it.
+    let server = serve_artifacts().max_block_size(8192);
+    let (client, server_thread) = spawn_with(server, |builder| builder.block_size(65464));
+
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::Octet, Vec::new())
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_size() {
+    let (client, server_thread) = spawn_with(serve_artifacts(), |builder| {
+        builder.timeout(Duration::from_secs(2))
+    });
+
+    let size = client.size("alice-in-wonderland.txt", Mode::Octet).unwrap();
+    assert_eq!(size, Some(EXEMPLAR.len() as u64));
+
+    // The probe aborts the transfer once the size is known.
     server_thread.join().unwrap().unwrap_err();
+
+    let (client, server_thread) = spawn(serve_artifacts());
+
+    let actual = Vec::with_capacity(size.unwrap() as usize);
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::Octet, actual)
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_get_with_size() {
+    let (client, server_thread) = spawn(serve_artifacts());
+
+    let mut announced = None;
+    let actual = client
+        .get_with_size(
+            "alice-in-wonderland.txt",
+            Mode::Octet,
+            Vec::new(),
+            |size, writer: &mut Vec<u8>| {
+                announced = size;
+                writer.reserve_exact(size.unwrap() as usize);
+                assert!(writer.is_empty());
+            },
+        )
+        .unwrap();
+    assert_eq!(announced, Some(EXEMPLAR.len() as u64));
+    assert_eq!(&actual[..], EXEMPLAR);
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_get_with_window_size() {
+    let (client, server_thread) = spawn_with(serve_artifacts(), |builder| builder.window_size(8));
+
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::Octet, Vec::new())
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_get_with_rollover() {
+    let (client, server_thread) =
+        spawn_with(serve_artifacts(), |builder| builder.rollover(Rollover::One));
+
+    let actual = client
+        .get("alice-in-wonderland.txt", Mode::Octet, Vec::new())
+        .unwrap();
+    assert_eq!(&actual[..], EXEMPLAR);
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_get_with_unacknowledged_rollover() {
+    // A server that knows nothing of rollover, and so wraps around to 0.
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let blocks: u32 = 65537;
+
+    let server_thread = thread::spawn(move || {
+        let mut buf = [0; 1024];
+        let (_, client) = listener.recv_from(&mut buf).unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(client).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut options = Options::new();
+        options.insert("blksize", "8");
+        let mut packet = AnyPacket::from(Packet::oack(options)).into_bytes();
+
+        for n in 0..=blocks + 1 {
+            let ack = AnyPacket::from(Packet::ack(Block::new(n as u16))).into_bytes();
+            for tries in 0.. {
+                assert!(tries < 5, "block {} was never acknowledged", n);
+                socket.send(&packet).unwrap();
+                let len = socket.recv(&mut buf).unwrap();
+                if buf[..len] == ack[..] {
+                    break;
+                }
+            }
+
+            let data = if n < blocks {
+                vec![(n + 1) as u8; 8]
+            } else {
+                vec![]
+            };
+            packet = AnyPacket::from(Packet::data(Block::new((n + 1) as u16), data)).into_bytes();
+        }
+    });
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap()
+        .block_size(8)
+        .rollover(Rollover::One)
+        .build();
+
+    let actual = client.get("big.bin", Mode::Octet, Vec::new()).unwrap();
+    let expected: Vec<u8> = (1..=blocks).flat_map(|n| vec![n as u8; 8]).collect();
+    assert!(actual == expected);
+
+    server_thread.join().unwrap();
+}
+
+#[test]
+fn test_get_lenient() {
+    let serve_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/artifacts");
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir).unwrap();
+    let server = server.strictness(Strictness::Lenient);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle()
+    });
+
+    // A read request whose mode lacks its nul terminator.
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .send_to(
+            b"\x00\x01alice-in-wonderland.txt\x00octet",
+            ("127.0.0.1", port),
+        )
+        .unwrap();
+
+    let mut buf = [0; 1024];
+    let (nbytes, server_addr) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(nbytes, 4 + 512);
+    assert_eq!(&buf[..4], &[0, 3, 0, 1]);
+
+    socket
+        .send_to(b"\x00\x05\x00\x00done\x00", server_addr)
+        .unwrap();
+    let error = server_thread.join().unwrap().unwrap_err();
+    assert!(matches!(error, Error::Remote { .. }));
 }
@@ -0,0 +1,129 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use tftp::packet::{AnyPacket, Block, Code, DataRef, Mode, Options, Packet, RqRef, Strictness};
+
+fn text() -> impl Strategy<Value = String> {
+    "[^\x00]{0,32}"
+}
+
+/// Option names and values, which a nul byte sneaks into every so often.
+fn option_text() -> impl Strategy<Value = String> {
+    r"(.|\x00){0,32}"
+}
+
+fn filename() -> impl Strategy<Value = Vec<u8>> {
+    vec(1u8.., 0..32)
+}
+
+fn mode() -> impl Strategy<Value = Mode> {
+    prop_oneof![Just(Mode::Mail), Just(Mode::NetAscii), Just(Mode::Octet)]
+}
+
+fn code() -> impl Strategy<Value = Code> {
+    prop_oneof![
+        Just(Code::NotDefined),
+        Just(Code::FileNotFound),
+        Just(Code::AccessViolation),
+        Just(Code::DiskFull),
+        Just(Code::IllegalOperation),
+        Just(Code::UnknownTid),
+        Just(Code::FileAlreadyExists),
+        Just(Code::NoSuchUser),
+        Just(Code::OptionNegotiation),
+        (9u16..).prop_map(Code::Other),
+    ]
+}
+
+fn options() -> impl Strategy<Value = Options> {
+    vec((option_text(), option_text()), 0..4).prop_map(|pairs| {
+        let mut options = Options::new();
+        for (name, value) in pairs {
+            let _ = options.try_insert(name, value);
+        }
+        options
+    })
+}
+
+fn packet() -> impl Strategy<Value = AnyPacket> {
+    prop_oneof![
+        (filename(), mode(), options())
+            .prop_map(|(f, m, o)| Packet::try_rrq(f, m, o).unwrap().into()),
+        (filename(), mode(), options())
+            .prop_map(|(f, m, o)| Packet::try_wrq(f, m, o).unwrap().into()),
+        (any::<u16>(), vec(any::<u8>(), 0..1024))
+            .prop_map(|(b, d)| Packet::data(Block::new(b), d).into()),
+        any::<u16>().prop_map(|b| Packet::ack(Block::new(b)).into()),
+        (code(), text()).prop_map(|(c, m)| Packet::error(c, m).into()),
+        options().prop_map(|o| Packet::oack(o).into()),
+        (7u16.., vec(any::<u8>(), 0..32))
+            .prop_map(|(opcode, body)| AnyPacket::Raw { opcode, body }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_round_trip(packet in packet()) {
+        let bytes = packet.clone().into_bytes();
+        prop_assert_eq!(AnyPacket::from_bytes(bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_views_agree(packet in packet()) {
+        let bytes = packet.clone().into_bytes();
+        let mut buf = vec![0; bytes.len()];
+
+        match packet {
+            AnyPacket::Data(ref data) => {
+                let view = DataRef::parse(&bytes).unwrap();
+                prop_assert_eq!(&view.to_packet(), data);
+                prop_assert_eq!(view.encode(&mut buf).unwrap(), bytes.len());
+                prop_assert_eq!(&buf, &bytes);
+            }
+            AnyPacket::Rrq(_) | AnyPacket::Wrq(_) => {
+                let view = RqRef::parse(&bytes).unwrap();
+                prop_assert_eq!(view.encode(&mut buf).unwrap(), bytes.len());
+                prop_assert_eq!(&buf, &bytes);
+            }
+            _ => {
+                prop_assert!(DataRef::parse(&bytes).is_err());
+                prop_assert!(RqRef::parse(&bytes).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_options_refuse_nul(name in option_text(), value in option_text()) {
+        let has_nul = name.contains('\0') || value.contains('\0');
+
+        let mut options = Options::new();
+        prop_assert_eq!(options.try_insert(&name, &value).is_err(), has_nul);
+        prop_assert_eq!(options.is_empty(), has_nul);
+    }
+
+    #[test]
+    fn test_arbitrary_bytes(bytes in vec(any::<u8>(), 0..64)) {
+        // Whatever parses has to survive being encoded and parsed again.
+        if let Ok(packet) = AnyPacket::from_bytes(&bytes) {
+            let reparsed = AnyPacket::from_bytes(packet.clone().into_bytes()).unwrap();
+            prop_assert_eq!(reparsed, packet.clone());
+
+            // Leniency only ever accepts more.
+            let lenient = AnyPacket::from_bytes_with(&bytes, Strictness::Lenient).unwrap();
+            prop_assert_eq!(lenient, packet);
+        }
+
+        let _ = DataRef::parse(&bytes);
+        let _ = RqRef::parse(&bytes);
+    }
+
+    #[test]
+    fn test_truncated(packet in packet(), len in any::<prop::sample::Index>()) {
+        let bytes = packet.into_bytes();
+        let bytes = &bytes[..len.index(bytes.len() + 1)];
+
+        let _ = AnyPacket::from_bytes(bytes);
+        let _ = DataRef::parse(bytes);
+        let _ = RqRef::parse(bytes);
+    }
+}
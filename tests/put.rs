@@ -1,8 +1,8 @@
 use std::{io, thread};
 
 use tftp::client;
-use tftp::packet::Mode;
-use tftp::Server;
+use tftp::packet::{Code, Mode};
+use tftp::{Error, Server};
 
 #[test]
 fn test_put() {
@@ -43,6 +43,7 @@ fn test_put() {
 
 struct ErroneousReader;
 
+#[allow(clippy::io_other_error, clippy::useless_format)]
 impl io::Read for ErroneousReader {
     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
         Err(io::Error::new(io::ErrorKind::Other, format!("Fake error")))
@@ -120,3 +121,188 @@ fn test_put_when_already_exists() {
         .put("alice-in-wonderland.txt", Mode::NetAscii, &data[..])
         .unwrap();
 }
+
+#[test]
+fn test_put_with_block_size() {
+    let serve_dir = tempfile::tempdir().unwrap();
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir.path()).unwrap();
+    let server_addr = format!("127.0.0.1:{}", port);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap();
+    });
+
+    // A multiple of the block size ends with an empty `Data` packet.
+    let data = vec![0x5a; 4 * 1024];
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap()
+        .block_size(1024)
+        .build();
+
+    client.put("blocks.bin", Mode::Octet, &data[..]).unwrap();
+    server_thread.join().unwrap();
+
+    let actual = std::fs::read(serve_dir.path().join("blocks.bin")).unwrap();
+    assert_eq!(actual, data);
+}
+
+#[test]
+fn test_put_with_size() {
+    let serve_dir = tempfile::tempdir().unwrap();
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir.path()).unwrap();
+    let server = server.max_transfer_size(1024);
+    let server_addr = format!("127.0.0.1:{}", port);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap();
+
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap_err();
+    });
+
+    let builder = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap();
+
+    let data = vec![0x5a; 1024];
+    builder
+        .try_clone()
+        .unwrap()
+        .build()
+        .put_with_size("small.bin", Mode::Octet, &data[..], 1024)
+        .unwrap();
+
+    // Files the server cannot accept are refused before any data is sent.
+    let data = vec![0x5a; 1025];
+    let error = builder
+        .build()
+        .put_with_size("large.bin", Mode::Octet, &data[..], 1025)
+        .unwrap_err();
+    match error {
+        Error::Remote { code, message } => {
+            assert_eq!(code, Code::DiskFull);
+            assert_eq!(message, "Disk full or allocation exceeded");
+        }
+        error => panic!("unexpected error: {:?}", error),
+    }
+
+    server_thread.join().unwrap();
+
+    assert!(serve_dir.path().join("small.bin").exists());
+    assert!(!serve_dir.path().join("large.bin").exists());
+}
+
+#[test]
+fn test_put_more_than_announced() {
+    let serve_dir = tempfile::tempdir().unwrap();
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir.path()).unwrap();
+    let server_addr = format!("127.0.0.1:{}", port);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap_err();
+    });
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap()
+        .build();
+
+    // The upload is cut off as soon as it grows past the announced size.
+    let data = vec![0x5a; 2048];
+    let error = client
+        .put_with_size("liar.bin", Mode::Octet, &data[..], 10)
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::Remote {
+            code: Code::DiskFull,
+            ..
+        }
+    ));
+
+    server_thread.join().unwrap();
+    assert!(!serve_dir.path().join("liar.bin").exists());
+}
+
+#[test]
+fn test_put_with_window_size() {
+    let serve_dir = tempfile::tempdir().unwrap();
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir.path()).unwrap();
+    let server = server.max_window_size(4);
+    let server_addr = format!("127.0.0.1:{}", port);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap();
+    });
+
+    let data = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/artifacts/alice-in-wonderland.txt"
+    ));
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap()
+        .window_size(16)
+        .build();
+
+    client
+        .put("alice-in-wonderland.txt", Mode::Octet, &data[..])
+        .unwrap();
+    server_thread.join().unwrap();
+
+    let actual = std::fs::read(serve_dir.path().join("alice-in-wonderland.txt")).unwrap();
+    assert_eq!(&actual[..], &data[..]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_put_raw_filename() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let serve_dir = tempfile::tempdir().unwrap();
+    let (port, server) = Server::random_port("127.0.0.1", serve_dir.path()).unwrap();
+    let server_addr = format!("127.0.0.1:{}", port);
+
+    let server_thread = thread::spawn(move || {
+        let handler = server.serve().unwrap();
+        handler.handle().unwrap();
+    });
+
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to(server_addr)
+        .unwrap()
+        .build();
+
+    // A Latin-1 filename, which is not valid UTF-8.
+    let filename = b"caf\xe9.txt";
+    client.put(filename, Mode::Octet, &b"latte"[..]).unwrap();
+    server_thread.join().unwrap();
+
+    let path = serve_dir.path().join(OsStr::from_bytes(filename));
+    assert_eq!(std::fs::read(path).unwrap(), b"latte");
+}
+
+#[test]
+fn test_put_nul_filename() {
+    let client = client::Builder::new()
+        .unwrap()
+        .connect_to("127.0.0.1:69")
+        .unwrap()
+        .build();
+
+    let error = client.put("a\0b", Mode::Octet, &b""[..]).unwrap_err();
+    assert!(matches!(error, Error::InvalidFilename));
+}
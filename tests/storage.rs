@@ -0,0 +1,140 @@
+mod common;
+
+use std::io::{self, Cursor};
+use std::path::Path;
+
+use tftp::access::Glob;
+use tftp::packet::{Code, Mode};
+use tftp::storage::{Generated, Memory, Overlay, Overwrite, ReadFile};
+use tftp::Server;
+
+use common::{assert_refused, spawn};
+
+#[test]
+fn test_get_from_memory() {
+    let memory = Memory::new();
+    memory.insert("boot/pxelinux.0", &b"not really a boot loader"[..]);
+
+    let server = Server::with_storage("127.0.0.1:0", memory).unwrap();
+    let (client, server_thread) = spawn(server);
+
+    let actual = client
+        .get("boot/pxelinux.0", Mode::Octet, Vec::new())
+        .unwrap();
+    assert_eq!(actual, b"not really a boot loader");
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_put_to_memory() {
+    let memory = Memory::new();
+    let server = Server::with_storage("127.0.0.1:0", memory.clone()).unwrap();
+    let (client, server_thread) = spawn(server);
+
+    let data: Vec<u8> = (0..=255).cycle().take(2000).collect();
+    client.put("upload.bin", Mode::Octet, &data[..]).unwrap();
+
+    server_thread.join().unwrap().unwrap();
+    assert_eq!(memory.get("upload.bin").unwrap(), data);
+}
+
+struct ErroneousReader;
+
+impl io::Read for ErroneousReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("Fake error"))
+    }
+}
+
+#[test]
+fn test_put_aborted() {
+    let memory = Memory::new();
+    let server = Server::with_storage("127.0.0.1:0", memory.clone()).unwrap();
+    let (client, server_thread) = spawn(server);
+
+    client
+        .put("broken.bin", Mode::Octet, ErroneousReader)
+        .unwrap_err();
+
+    server_thread.join().unwrap().unwrap_err();
+    assert!(memory.get("broken.bin").is_none());
+}
+
+#[test]
+fn test_overlay_keeps_lower_read_only() {
+    let lower = Memory::new();
+    lower.insert("config", &b"lower"[..]);
+    let upper = Memory::new();
+
+    let server = Server::with_storage("127.0.0.1:0", Overlay::new(upper, lower.clone())).unwrap();
+    let (client, server_thread) = spawn(server);
+
+    assert_refused(
+        client.put("config", Mode::Octet, &b"upper"[..]),
+        Code::FileAlreadyExists,
+    );
+
+    server_thread.join().unwrap().unwrap_err();
+    assert_eq!(lower.get("config").unwrap(), b"lower");
+}
+
+#[test]
+fn test_get_generated() {
+    let storage = Generated::new(
+        |name: &Path, client: std::net::SocketAddr| {
+            let mac = name.strip_prefix("pxelinux.cfg").ok()?;
+            let config = format!("# {} at {}\nDEFAULT local\n", mac.display(), client.ip());
+            Some(Ok(Box::new(Cursor::new(config)) as Box<dyn ReadFile>))
+        },
+        Memory::new(),
+    );
+
+    let server = Server::with_storage("127.0.0.1:0", storage).unwrap();
+    let (client, server_thread) = spawn(server);
+
+    let actual = client
+        .get(
+            "pxelinux.cfg/01-52-54-00-12-34-56",
+            Mode::NetAscii,
+            Vec::new(),
+        )
+        .unwrap();
+    assert_eq!(
+        actual,
+        b"# 01-52-54-00-12-34-56 at 127.0.0.1\nDEFAULT local\n"
+    );
+
+    server_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_overwrite_matching() {
+    let memory = Memory::new();
+    let server = || {
+        Server::with_storage("127.0.0.1:0", memory.clone())
+            .unwrap()
+            .overwrite_matching(Glob::new("backups/*.cfg"), Overwrite::Rotate(1))
+    };
+
+    for day in ["monday", "tuesday", "wednesday"] {
+        let (client, server_thread) = spawn(server());
+        client
+            .put("backups/switch.cfg", Mode::Octet, day.as_bytes())
+            .unwrap();
+        server_thread.join().unwrap().unwrap();
+    }
+    assert_eq!(memory.get("backups/switch.cfg").unwrap(), b"wednesday");
+    assert_eq!(memory.get("backups/switch.cfg.1").unwrap(), b"tuesday");
+    assert_eq!(memory.get("backups/switch.cfg.2"), None);
+
+    // Every other file is still refused.
+    memory.insert("firmware.bin", &b"firmware"[..]);
+    let (client, server_thread) = spawn(server());
+    assert_refused(
+        client.put("firmware.bin", Mode::Octet, &b"over"[..]),
+        Code::FileAlreadyExists,
+    );
+    server_thread.join().unwrap().unwrap_err();
+    assert_eq!(memory.get("firmware.bin").unwrap(), b"firmware");
+}